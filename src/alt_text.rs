@@ -0,0 +1,1252 @@
+//! Plain text versions of LaTeX and typst math, read to screen reader users as alt text and
+//! offered for copying.
+//!
+//! Both engines are parsed into the same small tree, which only knows the constructs that change
+//! how a formula reads: fractions, roots, scripts, accents and named symbols. Everything else is
+//! kept as written.
+
+use crate::render::Engine;
+
+/// Discord limits attachment descriptions to this many characters.
+pub const MAX_DESCRIPTION_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltText {
+    /// How the formula is read out, e.g. "x squared plus 1".
+    pub spoken: String,
+    /// The formula written with Unicode symbols, e.g. "x² + 1".
+    pub unicode: String,
+}
+
+impl AltText {
+    /// The spoken form, shortened to fit into an attachment description.
+    pub fn description(&self) -> String {
+        if self.spoken.chars().count() <= MAX_DESCRIPTION_LENGTH {
+            return self.spoken.clone();
+        }
+        let mut description = self
+            .spoken
+            .chars()
+            .take(MAX_DESCRIPTION_LENGTH - 1)
+            .collect::<String>();
+        description.push('…');
+        description
+    }
+}
+
+pub fn convert(source: &str, engine: Engine) -> AltText {
+    let nodes = match engine {
+        Engine::Latex => LatexParser::new(source).sequence(None),
+        Engine::Typst => TypstParser::new(source).sequence(&[]),
+    };
+
+    let mut words = Vec::new();
+    spoken_words(&nodes, &mut words);
+
+    AltText {
+        spoken: join_words(&words),
+        unicode: collapse_spaces(&unicode(&nodes)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Letters, digits, operators and whitespace, kept as written.
+    Text(String),
+    Symbol(&'static Symbol),
+    Fraction(Vec<Node>, Vec<Node>),
+    Binomial(Vec<Node>, Vec<Node>),
+    Root {
+        index: Option<Vec<Node>>,
+        radicand: Vec<Node>,
+    },
+    Superscript(Vec<Node>),
+    Subscript(Vec<Node>),
+    Accent(Vec<Node>, &'static Accent),
+    /// Content without delimiters, like a LaTeX `{...}` group.
+    Group(Vec<Node>),
+    /// Content in parentheses.
+    Parens(Vec<Node>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Symbol {
+    /// Command name without backslash, empty if LaTeX has none.
+    latex: &'static str,
+    /// Symbol name in typst, empty if typst has none.
+    typst: &'static str,
+    unicode: &'static str,
+    spoken: &'static str,
+    /// Big operators read their scripts as "from" and "to".
+    big: bool,
+}
+
+const fn symbol(
+    latex: &'static str,
+    typst: &'static str,
+    unicode: &'static str,
+    spoken: &'static str,
+) -> Symbol {
+    Symbol {
+        latex,
+        typst,
+        unicode,
+        spoken,
+        big: false,
+    }
+}
+
+const fn big(
+    latex: &'static str,
+    typst: &'static str,
+    unicode: &'static str,
+    spoken: &'static str,
+) -> Symbol {
+    Symbol {
+        latex,
+        typst,
+        unicode,
+        spoken,
+        big: true,
+    }
+}
+
+const SYMBOLS: &[Symbol] = &[
+    symbol("alpha", "alpha", "α", "alpha"),
+    symbol("beta", "beta", "β", "beta"),
+    symbol("gamma", "gamma", "γ", "gamma"),
+    symbol("delta", "delta", "δ", "delta"),
+    symbol("epsilon", "epsilon", "ε", "epsilon"),
+    symbol("varepsilon", "epsilon.alt", "ϵ", "epsilon"),
+    symbol("zeta", "zeta", "ζ", "zeta"),
+    symbol("eta", "eta", "η", "eta"),
+    symbol("theta", "theta", "θ", "theta"),
+    symbol("iota", "iota", "ι", "iota"),
+    symbol("kappa", "kappa", "κ", "kappa"),
+    symbol("lambda", "lambda", "λ", "lambda"),
+    symbol("mu", "mu", "μ", "mu"),
+    symbol("nu", "nu", "ν", "nu"),
+    symbol("xi", "xi", "ξ", "xi"),
+    symbol("pi", "pi", "π", "pi"),
+    symbol("rho", "rho", "ρ", "rho"),
+    symbol("sigma", "sigma", "σ", "sigma"),
+    symbol("tau", "tau", "τ", "tau"),
+    symbol("upsilon", "upsilon", "υ", "upsilon"),
+    symbol("phi", "phi", "φ", "phi"),
+    symbol("varphi", "phi.alt", "ϕ", "phi"),
+    symbol("chi", "chi", "χ", "chi"),
+    symbol("psi", "psi", "ψ", "psi"),
+    symbol("omega", "omega", "ω", "omega"),
+    symbol("Gamma", "Gamma", "Γ", "capital gamma"),
+    symbol("Delta", "Delta", "Δ", "capital delta"),
+    symbol("Theta", "Theta", "Θ", "capital theta"),
+    symbol("Lambda", "Lambda", "Λ", "capital lambda"),
+    symbol("Xi", "Xi", "Ξ", "capital xi"),
+    symbol("Pi", "Pi", "Π", "capital pi"),
+    symbol("Sigma", "Sigma", "Σ", "capital sigma"),
+    symbol("Phi", "Phi", "Φ", "capital phi"),
+    symbol("Psi", "Psi", "Ψ", "capital psi"),
+    symbol("Omega", "Omega", "Ω", "capital omega"),
+    symbol("cdot", "dot.op", "⋅", "times"),
+    symbol("times", "times", "×", "times"),
+    symbol("div", "div", "÷", "divided by"),
+    symbol("pm", "plus.minus", "±", "plus or minus"),
+    symbol("mp", "minus.plus", "∓", "minus or plus"),
+    symbol("leq", "lt.eq", "≤", "is less than or equal to"),
+    symbol("le", "", "≤", "is less than or equal to"),
+    symbol("geq", "gt.eq", "≥", "is greater than or equal to"),
+    symbol("ge", "", "≥", "is greater than or equal to"),
+    symbol("neq", "eq.not", "≠", "is not equal to"),
+    symbol("ne", "", "≠", "is not equal to"),
+    symbol("ll", "lt.double", "≪", "is much less than"),
+    symbol("gg", "gt.double", "≫", "is much greater than"),
+    symbol("approx", "approx", "≈", "is approximately"),
+    symbol("equiv", "equiv", "≡", "is equivalent to"),
+    symbol("sim", "tilde.op", "∼", "is similar to"),
+    symbol("propto", "prop", "∝", "is proportional to"),
+    symbol("in", "in", "∈", "in"),
+    symbol("notin", "in.not", "∉", "not in"),
+    symbol("subset", "subset", "⊂", "is a subset of"),
+    symbol("subseteq", "subset.eq", "⊆", "is a subset of or equal to"),
+    symbol("supset", "supset", "⊃", "is a superset of"),
+    symbol("cup", "union", "∪", "union"),
+    symbol("cap", "sect", "∩", "intersection"),
+    symbol("setminus", "without", "∖", "without"),
+    symbol("emptyset", "emptyset", "∅", "the empty set"),
+    symbol("forall", "forall", "∀", "for all"),
+    symbol("exists", "exists", "∃", "there exists"),
+    symbol("neg", "not", "¬", "not"),
+    symbol("land", "and", "∧", "and"),
+    symbol("wedge", "and", "∧", "and"),
+    symbol("lor", "or", "∨", "or"),
+    symbol("vee", "or", "∨", "or"),
+    symbol("to", "arrow.r", "→", "to"),
+    symbol("rightarrow", "", "→", "to"),
+    symbol("leftarrow", "arrow.l", "←", "from"),
+    symbol("gets", "", "←", "from"),
+    symbol("Rightarrow", "arrow.r.double", "⇒", "implies"),
+    symbol("implies", "", "⇒", "implies"),
+    symbol("Leftarrow", "arrow.l.double", "⇐", "is implied by"),
+    symbol("Leftrightarrow", "arrow.l.r.double", "⇔", "if and only if"),
+    symbol("iff", "", "⇔", "if and only if"),
+    symbol("mapsto", "arrow.r.bar", "↦", "maps to"),
+    symbol("infty", "infinity", "∞", "infinity"),
+    symbol("", "oo", "∞", "infinity"),
+    symbol("", "NN", "ℕ", "the natural numbers"),
+    symbol("", "ZZ", "ℤ", "the integers"),
+    symbol("", "QQ", "ℚ", "the rational numbers"),
+    symbol("", "RR", "ℝ", "the real numbers"),
+    symbol("", "CC", "ℂ", "the complex numbers"),
+    symbol("partial", "diff", "∂", "partial"),
+    symbol("nabla", "nabla", "∇", "nabla"),
+    symbol("hbar", "planck.reduce", "ℏ", "h bar"),
+    symbol("ell", "ell", "ℓ", "ell"),
+    symbol("circ", "compose", "∘", "composed with"),
+    symbol("cdots", "dots.h.c", "⋯", "dot dot dot"),
+    symbol("ldots", "dots.h", "…", "dot dot dot"),
+    symbol("dots", "dots", "…", "dot dot dot"),
+    symbol("angle", "angle", "∠", "angle"),
+    symbol("perp", "perp", "⊥", "is perpendicular to"),
+    symbol("parallel", "parallel", "∥", "is parallel to"),
+    symbol("sin", "sin", "sin", "sine"),
+    symbol("cos", "cos", "cos", "cosine"),
+    symbol("tan", "tan", "tan", "tangent"),
+    symbol("log", "log", "log", "log"),
+    symbol("ln", "ln", "ln", "natural log"),
+    symbol("exp", "exp", "exp", "exp"),
+    symbol("det", "det", "det", "determinant"),
+    symbol("max", "max", "max", "max"),
+    symbol("min", "min", "min", "min"),
+    big("sum", "sum", "∑", "the sum"),
+    big("prod", "product", "∏", "the product"),
+    big("int", "integral", "∫", "the integral"),
+    big("iint", "integral.double", "∬", "the double integral"),
+    big("oint", "integral.cont", "∮", "the contour integral"),
+    big("lim", "lim", "lim", "the limit"),
+    big("bigcup", "union.big", "⋃", "the union"),
+    big("bigcap", "sect.big", "⋂", "the intersection"),
+];
+
+/// Typst shorthands for symbols, longest first so prefixes do not match early.
+const TYPST_SHORTHANDS: &[(&str, &str)] = &[
+    ("<==>", "arrow.l.r.double"),
+    ("|->", "arrow.r.bar"),
+    ("...", "dots"),
+    ("->", "arrow.r"),
+    ("<-", "arrow.l"),
+    ("=>", "arrow.r.double"),
+    ("<=", "lt.eq"),
+    (">=", "gt.eq"),
+    ("!=", "eq.not"),
+    ("<<", "lt.double"),
+    (">>", "gt.double"),
+];
+
+fn latex_symbol(name: &str) -> Option<&'static Symbol> {
+    SYMBOLS.iter().find(|it| it.latex == name)
+}
+
+/// Looks up a typst symbol, dropping modifiers we do not know from the end.
+fn typst_symbol(name: &str) -> Option<&'static Symbol> {
+    let mut name = name;
+    loop {
+        if let Some(symbol) = SYMBOLS.iter().find(|it| it.typst == name) {
+            return Some(symbol);
+        }
+        name = &name[..name.rfind('.')?];
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Accent {
+    latex: &'static str,
+    typst: &'static str,
+    combining: char,
+    /// Read after the accented content.
+    spoken: &'static str,
+}
+
+const ACCENTS: &[Accent] = &[
+    Accent {
+        latex: "hat",
+        typst: "hat",
+        combining: '\u{302}',
+        spoken: "hat",
+    },
+    Accent {
+        latex: "bar",
+        typst: "macron",
+        combining: '\u{304}',
+        spoken: "bar",
+    },
+    Accent {
+        latex: "overline",
+        typst: "overline",
+        combining: '\u{305}',
+        spoken: "bar",
+    },
+    Accent {
+        latex: "vec",
+        typst: "arrow",
+        combining: '\u{20D7}',
+        spoken: "vector",
+    },
+    Accent {
+        latex: "dot",
+        typst: "dot",
+        combining: '\u{307}',
+        spoken: "dot",
+    },
+    Accent {
+        latex: "ddot",
+        typst: "dot.double",
+        combining: '\u{308}',
+        spoken: "double dot",
+    },
+    Accent {
+        latex: "tilde",
+        typst: "tilde",
+        combining: '\u{303}',
+        spoken: "tilde",
+    },
+];
+
+/// LaTeX commands that only change the font or size of their argument.
+const LATEX_STYLES: &[&str] = &[
+    "text",
+    "textrm",
+    "textit",
+    "textbf",
+    "textsf",
+    "texttt",
+    "mathrm",
+    "mathit",
+    "mathbf",
+    "mathsf",
+    "mathtt",
+    "mathcal",
+    "mathbb",
+    "mathfrak",
+    "mathscr",
+    "boldsymbol",
+    "operatorname",
+    "mbox",
+];
+
+/// LaTeX commands without visible output of their own.
+const LATEX_IGNORED: &[&str] = &[
+    "displaystyle",
+    "textstyle",
+    "limits",
+    "nolimits",
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "bigl",
+    "bigr",
+    "Bigl",
+    "Bigr",
+];
+
+/// Typst functions that only change the font or size of their argument.
+const TYPST_STYLES: &[&str] = &[
+    "bold", "italic", "upright", "cal", "frak", "bb", "mono", "sans", "serif", "display", "inline",
+    "lr", "limits", "scripts", "op", "class",
+];
+
+/// Deepest nesting the parsers follow. The parsers run in the bot itself, so this keeps deeply
+/// nested input from overflowing the stack, anything nested deeper is kept as written.
+const MAX_DEPTH: usize = 64;
+
+/// Takes the rest of the input as plain text, once it is nested too deeply to parse.
+fn rest_as_text(chars: &[char], pos: &mut usize) -> Node {
+    let rest = chars[(*pos).min(chars.len())..].iter().collect();
+    *pos = chars.len();
+    Node::Text(rest)
+}
+
+struct LatexParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Number of groups and arguments currently being parsed.
+    depth: usize,
+}
+
+impl LatexParser {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses until the closing character, which is consumed, or the end of the input.
+    fn sequence(&mut self, close: Option<char>) -> Vec<Node> {
+        if self.depth >= MAX_DEPTH {
+            return vec![rest_as_text(&self.chars, &mut self.pos)];
+        }
+        self.depth += 1;
+        let nodes = self.sequence_inner(close);
+        self.depth -= 1;
+        nodes
+    }
+
+    fn sequence_inner(&mut self, close: Option<char>) -> Vec<Node> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                _ if Some(c) == close => break,
+                '{' => nodes.push(Node::Group(self.sequence(Some('}')))),
+                '}' => {}
+                '^' => nodes.push(Node::Superscript(self.argument())),
+                '_' => nodes.push(Node::Subscript(self.argument())),
+                '\\' => self.command(&mut nodes),
+                '%' => {
+                    while self.peek().is_some_and(|it| it != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                '$' | '&' => {}
+                '~' => push_text(&mut nodes, " "),
+                _ => push_text(&mut nodes, &c.to_string()),
+            }
+        }
+
+        nodes
+    }
+
+    /// A group or a single character or command, as taken by commands and scripts.
+    fn argument(&mut self) -> Vec<Node> {
+        if self.depth >= MAX_DEPTH {
+            return vec![rest_as_text(&self.chars, &mut self.pos)];
+        }
+        self.depth += 1;
+        let nodes = self.argument_inner();
+        self.depth -= 1;
+        nodes
+    }
+
+    fn argument_inner(&mut self) -> Vec<Node> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.sequence(Some('}'))
+            }
+            Some('\\') => {
+                self.pos += 1;
+                let mut nodes = Vec::new();
+                self.command(&mut nodes);
+                nodes
+            }
+            Some(c) => {
+                self.pos += 1;
+                vec![Node::Text(c.to_string())]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn optional_argument(&mut self) -> Option<Vec<Node>> {
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.sequence(Some(']')))
+    }
+
+    /// Parses a command after its backslash.
+    fn command(&mut self, nodes: &mut Vec<Node>) {
+        let start = self.pos;
+        while self.peek().is_some_and(|it| it.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let name = self.chars[start..self.pos].iter().collect::<String>();
+
+        if name.is_empty() {
+            let Some(c) = self.peek() else {
+                return;
+            };
+            self.pos += 1;
+            match c {
+                '\\' => push_text(nodes, "\n"),
+                ',' | ';' | ':' | ' ' => push_text(nodes, " "),
+                '!' | '[' | ']' | '(' | ')' => {}
+                _ => push_text(nodes, &c.to_string()),
+            }
+            return;
+        }
+
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                nodes.push(Node::Fraction(numerator, denominator));
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.argument();
+                let k = self.argument();
+                nodes.push(Node::Binomial(n, k));
+            }
+            "sqrt" => {
+                let index = self.optional_argument();
+                let radicand = self.argument();
+                nodes.push(Node::Root { index, radicand });
+            }
+            "begin" | "end" => {
+                self.argument();
+            }
+            "left" | "right" | "middle" => {
+                // `\left.` opens an invisible delimiter
+                self.skip_whitespace();
+                if self.peek() == Some('.') {
+                    self.pos += 1;
+                }
+            }
+            "quad" | "qquad" => push_text(nodes, " "),
+            _ if LATEX_STYLES.contains(&name.as_str()) => {
+                nodes.push(Node::Group(self.argument()));
+            }
+            _ if LATEX_IGNORED.contains(&name.as_str()) => {}
+            _ => {
+                if let Some(accent) = ACCENTS.iter().find(|it| it.latex == name) {
+                    nodes.push(Node::Accent(self.argument(), accent));
+                } else if let Some(symbol) = latex_symbol(&name) {
+                    nodes.push(Node::Symbol(symbol));
+                } else {
+                    push_text(nodes, &name);
+                }
+            }
+        }
+    }
+}
+
+/// Appends text, merging it with text right before.
+fn push_text(nodes: &mut Vec<Node>, text: &str) {
+    if let Some(Node::Text(last)) = nodes.last_mut() {
+        last.push_str(text);
+    } else {
+        nodes.push(Node::Text(text.to_string()));
+    }
+}
+
+struct TypstParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Number of sequences and atoms currently being parsed.
+    depth: usize,
+}
+
+impl TypstParser {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        let mut rest = self.chars[self.pos.min(self.chars.len())..].iter();
+        text.chars().all(|c| rest.next() == Some(&c))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses until one of the closing characters, which is left in the input, or the end.
+    fn sequence(&mut self, close: &[char]) -> Vec<Node> {
+        if self.depth >= MAX_DEPTH {
+            return vec![rest_as_text(&self.chars, &mut self.pos)];
+        }
+        self.depth += 1;
+        let nodes = self.sequence_inner(close);
+        self.depth -= 1;
+        nodes
+    }
+
+    fn sequence_inner(&mut self, close: &[char]) -> Vec<Node> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if close.contains(&c) {
+                break;
+            }
+
+            if c == '/' && !self.starts_with("//") {
+                self.pos += 1;
+                // Whitespace around the slash does not matter
+                while matches!(nodes.last(), Some(Node::Text(it)) if it.trim().is_empty()) {
+                    nodes.pop();
+                }
+                let numerator = pop_operand(&mut nodes);
+                self.skip_whitespace();
+                let denominator = self.atom().map(unwrap_parens).unwrap_or_default();
+                nodes.push(Node::Fraction(numerator, denominator));
+            } else if c == '^' || c == '_' {
+                self.pos += 1;
+                let script = self.atom().map(unwrap_parens).unwrap_or_default();
+                nodes.push(if c == '^' {
+                    Node::Superscript(script)
+                } else {
+                    Node::Subscript(script)
+                });
+            } else if let Some(node) = self.atom() {
+                nodes.push(node);
+            }
+        }
+
+        nodes
+    }
+
+    /// The next unit of input, `None` if it has no visible output.
+    fn atom(&mut self) -> Option<Node> {
+        self.peek()?;
+        if self.depth >= MAX_DEPTH {
+            return Some(rest_as_text(&self.chars, &mut self.pos));
+        }
+        self.depth += 1;
+        let node = self.atom_inner();
+        self.depth -= 1;
+        node
+    }
+
+    fn atom_inner(&mut self) -> Option<Node> {
+        let c = self.peek()?;
+
+        if self.starts_with("//") {
+            while self.peek().is_some_and(|it| it != '\n') {
+                self.pos += 1;
+            }
+            return None;
+        }
+        for (shorthand, name) in TYPST_SHORTHANDS {
+            if self.starts_with(shorthand) {
+                self.pos += shorthand.chars().count();
+                return typst_symbol(name).map(Node::Symbol);
+            }
+        }
+
+        self.pos += 1;
+        match c {
+            '(' => {
+                let inner = self.sequence(&[')']);
+                self.pos += 1;
+                Some(Node::Parens(inner))
+            }
+            '"' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|it| it != '"') {
+                    self.pos += 1;
+                }
+                let text = self.chars[start..self.pos].iter().collect();
+                self.pos += 1;
+                Some(Node::Text(text))
+            }
+            '\\' => match self.peek() {
+                Some(escaped) if !escaped.is_whitespace() => {
+                    self.pos += 1;
+                    Some(Node::Text(escaped.to_string()))
+                }
+                _ => Some(Node::Text("\n".to_string())),
+            },
+            '$' | '&' | '#' | ')' => None,
+            _ if c.is_alphabetic() => Some(self.identifier(c)),
+            _ if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(next) = self.peek() {
+                    let decimal_point = next == '.'
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(char::is_ascii_digit);
+                    if !next.is_ascii_digit() && !decimal_point {
+                        break;
+                    }
+                    number.push(next);
+                    self.pos += 1;
+                }
+                Some(Node::Text(number))
+            }
+            _ => Some(Node::Text(c.to_string())),
+        }
+    }
+
+    /// Parses an identifier, a symbol or a function call starting with `first`.
+    fn identifier(&mut self, first: char) -> Node {
+        let mut name = first.to_string();
+        while let Some(next) = self.peek() {
+            let modifier = next == '.'
+                && self
+                    .chars
+                    .get(self.pos + 1)
+                    .is_some_and(|it| it.is_alphabetic());
+            if !next.is_alphanumeric() && !modifier {
+                break;
+            }
+            name.push(next);
+            self.pos += 1;
+        }
+
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let mut args = self.arguments();
+            return match (name.as_str(), args.len()) {
+                ("sqrt", 1) => Node::Root {
+                    index: None,
+                    radicand: args.remove(0),
+                },
+                ("root", 2) => {
+                    let radicand = args.remove(1);
+                    Node::Root {
+                        index: Some(args.remove(0)),
+                        radicand,
+                    }
+                }
+                ("frac", 2) => {
+                    let denominator = args.remove(1);
+                    Node::Fraction(args.remove(0), denominator)
+                }
+                ("binom", 2) => {
+                    let k = args.remove(1);
+                    Node::Binomial(args.remove(0), k)
+                }
+                ("abs", 1) => {
+                    let mut inner = vec![Node::Text("|".to_string())];
+                    inner.append(&mut args.remove(0));
+                    inner.push(Node::Text("|".to_string()));
+                    Node::Group(inner)
+                }
+                (_, 1) if TYPST_STYLES.contains(&name.as_str()) => Node::Group(args.remove(0)),
+                (_, 1) if ACCENTS.iter().any(|it| it.typst == name) => {
+                    let accent = ACCENTS.iter().find(|it| it.typst == name).unwrap();
+                    Node::Accent(args.remove(0), accent)
+                }
+                _ => {
+                    let mut call = vec![Node::Text(name)];
+                    let mut inner = Vec::new();
+                    for (idx, arg) in args.into_iter().enumerate() {
+                        if idx > 0 {
+                            inner.push(Node::Text(", ".to_string()));
+                        }
+                        inner.push(Node::Group(arg));
+                    }
+                    call.push(Node::Parens(inner));
+                    Node::Group(call)
+                }
+            };
+        }
+
+        if name.chars().count() == 1 {
+            return Node::Text(name);
+        }
+        match typst_symbol(&name) {
+            Some(symbol) => Node::Symbol(symbol),
+            None => Node::Text(name),
+        }
+    }
+
+    /// Parses comma separated arguments up to and including the closing parenthesis.
+    fn arguments(&mut self) -> Vec<Vec<Node>> {
+        let mut args = Vec::new();
+        loop {
+            args.push(self.sequence(&[',', ')']));
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(_) => {
+                    self.pos += 1;
+                    return args;
+                }
+                None => return args,
+            }
+        }
+    }
+}
+
+/// Removes the last unit, including its base if it is a script, as the numerator of a fraction.
+fn pop_operand(nodes: &mut Vec<Node>) -> Vec<Node> {
+    let mut operand = Vec::new();
+    while let Some(node) = nodes.pop() {
+        let script = matches!(node, Node::Superscript(_) | Node::Subscript(_));
+        operand.insert(0, node);
+        if !script {
+            break;
+        }
+    }
+
+    match operand.len() {
+        1 => unwrap_parens(operand.remove(0)),
+        _ => operand,
+    }
+}
+
+/// Parentheses around fractions and scripts are only grouping in typst, they are not shown.
+fn unwrap_parens(node: Node) -> Vec<Node> {
+    match node {
+        Node::Parens(inner) => inner,
+        node => vec![node],
+    }
+}
+
+/// Whether nodes form a single unit that needs no parentheses when written inline.
+fn is_atomic(nodes: &[Node]) -> bool {
+    let mut visible = nodes
+        .iter()
+        .filter(|it| !matches!(it, Node::Text(text) if text.trim().is_empty()));
+    let (Some(node), None) = (visible.next(), visible.next()) else {
+        return false;
+    };
+
+    match node {
+        Node::Text(text) => text.trim().chars().all(char::is_alphanumeric),
+        Node::Symbol(_) | Node::Parens(_) => true,
+        Node::Group(inner) => is_atomic(inner),
+        _ => false,
+    }
+}
+
+fn unicode(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Symbol(symbol) => out.push_str(symbol.unicode),
+            Node::Fraction(numerator, denominator) => {
+                out.push_str(&unicode_operand(numerator));
+                out.push('/');
+                out.push_str(&unicode_operand(denominator));
+            }
+            Node::Binomial(n, k) => {
+                out.push_str(&format!("C({}, {})", unicode(n).trim(), unicode(k).trim()));
+            }
+            Node::Root { index, radicand } => {
+                let index = index.as_deref().map(unicode);
+                match index.as_deref().map(str::trim) {
+                    None | Some("2") => out.push('√'),
+                    Some("3") => out.push('∛'),
+                    Some("4") => out.push('∜'),
+                    Some(index) => {
+                        out.push_str(&script(index, superscript_char, "^"));
+                        out.push('√');
+                    }
+                }
+                out.push_str(&unicode_operand(radicand));
+            }
+            Node::Superscript(content) => {
+                out.push_str(&script(&unicode(content), superscript_char, "^"))
+            }
+            Node::Subscript(content) => {
+                out.push_str(&script(&unicode(content), subscript_char, "_"))
+            }
+            Node::Accent(content, accent) => {
+                out.push_str(unicode(content).trim());
+                out.push(accent.combining);
+            }
+            Node::Group(inner) => out.push_str(&unicode(inner)),
+            Node::Parens(inner) => {
+                out.push('(');
+                out.push_str(&unicode(inner));
+                out.push(')');
+            }
+        }
+    }
+    out
+}
+
+/// A fraction operand or radicand, in parentheses unless it is a single unit.
+fn unicode_operand(nodes: &[Node]) -> String {
+    let text = unicode(nodes);
+    let text = text.trim();
+    if is_atomic(nodes) {
+        text.to_string()
+    } else {
+        format!("({text})")
+    }
+}
+
+/// Writes a script with Unicode super- or subscript characters if all characters have one, and
+/// with a caret or underscore otherwise.
+fn script(content: &str, map: fn(char) -> Option<char>, marker: &str) -> String {
+    let content = content.trim();
+    if let Some(mapped) = content.chars().map(map).collect::<Option<String>>() {
+        return mapped;
+    }
+
+    if content.chars().count() == 1 {
+        format!("{marker}{content}")
+    } else {
+        format!("{marker}({content})")
+    }
+}
+
+fn superscript_char(c: char) -> Option<char> {
+    let mapped = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+fn subscript_char(c: char) -> Option<char> {
+    let mapped = match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+/// Collapses runs of spaces and trims every line.
+fn collapse_spaces(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn spoken_char(c: char) -> Option<&'static str> {
+    let spoken = match c {
+        '=' => "equals",
+        '+' => "plus",
+        '-' | '−' => "minus",
+        '*' => "times",
+        '/' => "divided by",
+        '<' => "is less than",
+        '>' => "is greater than",
+        '!' => "factorial",
+        '(' => "open paren",
+        ')' => "close paren",
+        '|' => "bar",
+        _ => return None,
+    };
+    Some(spoken)
+}
+
+fn spoken_words(nodes: &[Node], words: &mut Vec<String>) {
+    // Scripts of big operators are their bounds
+    let mut after_big = false;
+
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                if text.trim().is_empty() {
+                    continue;
+                }
+                spoken_text(text, words);
+            }
+            Node::Symbol(symbol) => words.push(symbol.spoken.to_string()),
+            Node::Fraction(numerator, denominator) => {
+                let simple = is_atomic(numerator) && is_atomic(denominator);
+                if !simple {
+                    words.push("the fraction".to_string());
+                }
+                spoken_words(numerator, words);
+                words.push("over".to_string());
+                spoken_words(denominator, words);
+                if !simple {
+                    words.push("end fraction".to_string());
+                }
+            }
+            Node::Binomial(n, k) => {
+                spoken_words(n, words);
+                words.push("choose".to_string());
+                spoken_words(k, words);
+            }
+            Node::Root { index, radicand } => {
+                let index_text = index.as_deref().map(unicode);
+                match index_text.as_deref().map(str::trim) {
+                    None | Some("2") => words.push("the square root of".to_string()),
+                    Some("3") => words.push("the cube root of".to_string()),
+                    Some(index) => words.push(format!("the {index}-th root of")),
+                }
+                spoken_words(radicand, words);
+                if !is_atomic(radicand) {
+                    words.push("end root".to_string());
+                }
+            }
+            Node::Superscript(content) if after_big => {
+                words.push("to".to_string());
+                spoken_words(content, words);
+                continue;
+            }
+            Node::Subscript(content) if after_big => {
+                words.push("from".to_string());
+                spoken_words(content, words);
+                continue;
+            }
+            Node::Superscript(content) => match unicode(content).trim() {
+                "2" => words.push("squared".to_string()),
+                "3" => words.push("cubed".to_string()),
+                _ => {
+                    words.push("to the power of".to_string());
+                    spoken_words(content, words);
+                    if !is_atomic(content) {
+                        words.push("end power".to_string());
+                    }
+                }
+            },
+            Node::Subscript(content) => {
+                words.push("sub".to_string());
+                spoken_words(content, words);
+            }
+            Node::Accent(content, accent) => {
+                spoken_words(content, words);
+                words.push(accent.spoken.to_string());
+            }
+            Node::Group(inner) => spoken_words(inner, words),
+            Node::Parens(inner) => {
+                words.push("open paren".to_string());
+                spoken_words(inner, words);
+                words.push("close paren".to_string());
+            }
+        }
+
+        after_big = matches!(node, Node::Symbol(symbol) if symbol.big);
+    }
+}
+
+/// Joins words with spaces, attaching punctuation to the word before.
+fn join_words(words: &[String]) -> String {
+    let mut spoken = String::new();
+    for word in words {
+        let punctuation = word.chars().all(|c| matches!(c, ',' | '.' | ';' | ':'));
+        if !spoken.is_empty() && !punctuation {
+            spoken.push(' ');
+        }
+        spoken.push_str(word);
+    }
+    spoken
+}
+
+/// Splits text into words, reading operators out.
+fn spoken_text(text: &str, words: &mut Vec<String>) {
+    let mut word = String::new();
+    for c in text.chars() {
+        let spoken = spoken_char(c);
+        if c.is_whitespace() || spoken.is_some() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            if let Some(spoken) = spoken {
+                words.push(spoken.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latex(source: &str) -> AltText {
+        convert(source, Engine::Latex)
+    }
+
+    fn typst(source: &str) -> AltText {
+        convert(source, Engine::Typst)
+    }
+
+    #[test]
+    fn latex_fractions() {
+        let simple = latex(r"\frac{a}{b}");
+        assert_eq!(simple.spoken, "a over b");
+        assert_eq!(simple.unicode, "a/b");
+
+        let compound = latex(r"\frac{a+1}{2}");
+        assert_eq!(compound.spoken, "the fraction a plus 1 over 2 end fraction");
+        assert_eq!(compound.unicode, "(a+1)/2");
+    }
+
+    #[test]
+    fn latex_scripts() {
+        let scripts = latex(r"x^2 + y_{i}");
+        assert_eq!(scripts.spoken, "x squared plus y sub i");
+        assert_eq!(scripts.unicode, "x² + yᵢ");
+
+        let sum = latex(r"\sum_{i=1}^n i");
+        assert_eq!(sum.spoken, "the sum from i equals 1 to n i");
+        assert_eq!(sum.unicode, "∑ᵢ₌₁ⁿ i");
+    }
+
+    #[test]
+    fn latex_roots() {
+        assert_eq!(latex(r"\sqrt[3]{x}").spoken, "the cube root of x");
+        assert_eq!(latex(r"\sqrt[3]{x}").unicode, "∛x");
+    }
+
+    #[test]
+    fn latex_delimiters() {
+        let parens = latex(r"\left( x \right)");
+        assert_eq!(parens.spoken, "open paren x close paren");
+        assert_eq!(parens.unicode, "( x )");
+
+        // `\left.` is an invisible delimiter
+        assert_eq!(latex(r"\left. x \right|").spoken, "x bar");
+    }
+
+    #[test]
+    fn latex_environments() {
+        let matrix = latex("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}");
+        assert_eq!(matrix.spoken, "a b c d");
+        assert_eq!(matrix.unicode, "a b\nc d");
+    }
+
+    #[test]
+    fn latex_unbalanced() {
+        assert_eq!(latex(r"\frac{a").unicode, "a/()");
+        assert_eq!(latex("x}^").unicode, "x");
+    }
+
+    #[test]
+    fn typst_function_calls() {
+        assert_eq!(typst("frac(a, b)").spoken, "a over b");
+        assert_eq!(
+            typst("sqrt(x) + root(3, y)").spoken,
+            "the square root of x plus the cube root of y"
+        );
+        assert_eq!(typst("abs(x)").unicode, "|x|");
+
+        let unknown = typst("f(x, y)");
+        assert_eq!(unknown.spoken, "f open paren x, y close paren");
+        assert_eq!(unknown.unicode, "f(x, y)");
+    }
+
+    #[test]
+    fn typst_fractions_and_scripts() {
+        assert_eq!(typst("(a + b)/2").unicode, "(a + b)/2");
+        assert_eq!(
+            typst("sum_(i=1)^n i").spoken,
+            "the sum from i equals 1 to n i"
+        );
+        assert_eq!(typst("\"text\" x").unicode, "text x");
+    }
+
+    #[test]
+    fn typst_unbalanced() {
+        assert_eq!(typst("frac(a, (b").unicode, "a/(b)");
+        assert_eq!(typst("x^").unicode, "x");
+    }
+
+    #[test]
+    fn deep_nesting_does_not_overflow() {
+        let inputs = [
+            ("(".repeat(4000), Engine::Typst),
+            ("frac(".repeat(2000), Engine::Typst),
+            ("x^(".repeat(2000), Engine::Typst),
+            ("{".repeat(4000), Engine::Latex),
+            ("^{".repeat(2000), Engine::Latex),
+            (r"\frac".repeat(2000), Engine::Latex),
+        ];
+
+        // Much less stack than the bot's worker threads have
+        std::thread::Builder::new()
+            .stack_size(512 * 1024)
+            .spawn(move || {
+                for (source, engine) in inputs {
+                    let alt_text = convert(&source, engine);
+                    assert!(!alt_text.unicode.is_empty());
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
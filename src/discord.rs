@@ -1,350 +1,2014 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use image::ImageFormat;
-use log::{info, trace, warn};
+use log::{error, info, trace, warn};
 use poise::serenity_prelude::{
-    self as serenity, ButtonStyle, ComponentInteraction, CreateActionRow, CreateAttachment,
-    CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
-    EditAttachments, EditMessage, FullEvent, GatewayIntents, Member, Message, MessageId,
-    ReactionType, User, UserId,
+    self as serenity, ActionRowComponent, Builder, ButtonKind, ButtonStyle, ChannelId,
+    ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateAttachment, CreateButton,
+    CreateCommand, CreateEmbed, CreateEmbedFooter, CreateInputText, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage,
+    CreateModal, EditAttachments, EditInteractionResponse, EditMessage, FullEvent, GatewayIntents,
+    GuildId, InputTextStyle, InstallationContext, InteractionContext, Message, MessageId,
+    ModalInteractionCollector, ModalInteractionData, Reaction, ReactionType, User, UserId,
 };
 use poise::{CreateReply, EditTracker, PrefixFrameworkOptions};
 use tokio::select;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::Mutex;
 
+use crate::alt_text;
+use crate::i18n::{self, Locale};
+use crate::preprocess;
+use crate::queue::{JobId, RenderQueue, Status};
+use crate::render::{self, Engine, Libraries, OutputFormat, RenderRequest, RenderedPart};
+use crate::settings::{self, EffectiveSettings, Scope};
+use crate::store::{HistoryEntry, RenderedResponse, Store, WidenInfo};
+use crate::theme::{Appearance, Theme};
 use crate::wolframalpha::{WolframAlpha, WolframAlphaSimpleResult};
-use crate::{latex, ImageWidth};
+use crate::ImageWidth;
+
+mod config;
+mod history;
+mod macros;
+mod modules;
+mod snippets;
+mod theme;
 
 const DELETE_CUSTOM_ID: &str = "delete";
 const WIDEN_CUSTOM_ID: &str = "widen";
+const NARROW_CUSTOM_ID: &str = "narrow";
+const CUSTOM_WIDTH_CUSTOM_ID: &str = "width";
+const EDIT_CUSTOM_ID: &str = "edit";
+const SOURCE_CUSTOM_ID: &str = "source";
+const TEXT_CUSTOM_ID: &str = "text";
+const CANCEL_CUSTOM_ID: &str = "cancel";
+const SWITCH_ENGINE_CUSTOM_ID: &str = "engine";
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct WidenInfo {
-    /// Owner of the original message.
-    owner: UserId,
-    /// LaTeX code used to generate the original response.
-    latex: String,
-}
+/// Longest message content Discord accepts.
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// How long a message has to stay unedited before it is rendered automatically.
+const AUTO_RENDER_DEBOUNCE: Duration = Duration::from_secs(2);
 
 pub struct BotContext {
     wolfram_alpha: WolframAlpha,
 
-    /// Maps from message (with math) to our response (usually with image).
-    rendered_cache: Arc<Mutex<HashMap<MessageId, MessageId>>>,
+    /// Persistent storage. It maps from message (with math) to our response (usually with image)
+    /// and from our response to the source it was rendered from.
+    store: Arc<Store>,
 
-    /// Maps from our response (usually with image) to widening information.
-    /// This info is only present if the image can be widened.
-    widen_cache: Arc<Mutex<HashMap<MessageId, WidenInfo>>>,
+    /// How long cached responses are remembered.
+    cache_ttl: Duration,
+
+    /// Latest generation of each message waiting for an automatic render, see
+    /// [`BotContext::debounce_auto_render`].
+    pending_auto_renders: std::sync::Mutex<HashMap<MessageId, u64>>,
+
+    /// Every render waits here for a free renderer, see [`render_queued`].
+    queue: RenderQueue,
 
     renderer_image: String,
 }
 
 impl BotContext {
-    async fn rendered_response_id(&self, message_id: MessageId) -> Option<MessageId> {
-        self.rendered_cache.lock().await.get(&message_id).copied()
+    async fn rendered_response_id(
+        &self,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<MessageId>> {
+        self.store.rendered_response_id(message_id).await
     }
 
-    async fn register_rendered_response_id(&self, message_id: MessageId, response_id: MessageId) {
-        self.rendered_cache
-            .lock()
+    async fn rendered_response(
+        &self,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<RenderedResponse>> {
+        self.store.rendered_response(message_id).await
+    }
+
+    async fn register_rendered_response(
+        &self,
+        message_id: MessageId,
+        response: RenderedResponse,
+    ) -> anyhow::Result<()> {
+        self.store
+            .register_rendered_response(message_id, response)
             .await
-            .insert(message_id, response_id);
     }
 
-    async fn widen_info(&self, message_id: MessageId) -> Option<WidenInfo> {
-        self.widen_cache.lock().await.get(&message_id).cloned()
+    async fn forget_rendered_response(&self, message_id: MessageId) -> anyhow::Result<()> {
+        self.store.forget_rendered_response(message_id).await
+    }
+
+    async fn set_rendered_engine(
+        &self,
+        response_id: MessageId,
+        engine: Engine,
+    ) -> anyhow::Result<()> {
+        self.store.set_rendered_engine(response_id, engine).await
+    }
+
+    /// Settings for a channel, inheriting from its guild.
+    async fn settings(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+    ) -> anyhow::Result<EffectiveSettings> {
+        let mut scopes = vec![self.store.settings(Scope::Channel(channel_id)).await?];
+        if let Some(guild_id) = guild_id {
+            scopes.push(self.store.settings(Scope::Guild(guild_id)).await?);
+        }
+        Ok(EffectiveSettings::resolve(&scopes))
+    }
+
+    async fn command_enabled(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        command: &str,
+    ) -> anyhow::Result<bool> {
+        let mut overrides = vec![
+            self.store
+                .command_enabled(Scope::Channel(channel_id), command)
+                .await?,
+        ];
+        if let Some(guild_id) = guild_id {
+            overrides.push(
+                self.store
+                    .command_enabled(Scope::Guild(guild_id), command)
+                    .await?,
+            );
+        }
+        Ok(settings::command_enabled(&overrides))
+    }
+
+    /// Macros and typst modules available in renders of a user, see [`crate::macros::merge`].
+    async fn libraries(
+        &self,
+        guild_id: Option<GuildId>,
+        user_id: UserId,
+    ) -> anyhow::Result<Libraries> {
+        let user_macros = self.store.macros(user_id.get()).await?;
+        let (guild_macros, typst_modules) = match guild_id {
+            Some(guild_id) => (
+                self.store.macros(guild_id.get()).await?,
+                self.store.typst_modules(guild_id).await?,
+            ),
+            None => Default::default(),
+        };
+        let macros = crate::macros::merge(user_macros, guild_macros);
+
+        Ok(Libraries {
+            latex_preamble: crate::macros::preamble(&macros),
+            typst_modules,
+        })
+    }
+
+    /// Default appearance of renders requested by a user.
+    async fn appearance(&self, user_id: UserId) -> anyhow::Result<Appearance> {
+        Ok(self.store.appearance(user_id).await?.unwrap_or_default())
+    }
+
+    /// Language of responses: the one configured for the channel or server, otherwise the one
+    /// the user picked in Discord if we speak it.
+    async fn locale(
+        &self,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user_locale: Option<&str>,
+    ) -> anyhow::Result<Locale> {
+        let settings = self.settings(guild_id, channel_id).await?;
+        Ok(settings
+            .locale
+            .or_else(|| user_locale.and_then(Locale::from_discord))
+            .unwrap_or_default())
+    }
+
+    async fn detach_response(&self, response_id: MessageId) -> anyhow::Result<()> {
+        self.store.detach_response(response_id).await
+    }
+
+    async fn forget_response(&self, response_id: MessageId) -> anyhow::Result<()> {
+        self.store.forget_response(response_id).await
+    }
+
+    async fn widen_info(&self, response_id: MessageId) -> anyhow::Result<Option<WidenInfo>> {
+        self.store.widen_info(response_id).await
+    }
+
+    async fn register_widen_info(
+        &self,
+        response_id: MessageId,
+        info: WidenInfo,
+    ) -> anyhow::Result<()> {
+        self.store.register_widen_info(response_id, info).await
+    }
+}
+
+impl BotContext {
+    /// Waits until a message was left alone for a moment. Returns false if the message was edited
+    /// or deleted in the meantime, in which case the newer event takes over.
+    async fn debounce_auto_render(&self, message_id: MessageId) -> bool {
+        let generation = {
+            let mut pending = self.pending_auto_renders.lock().unwrap();
+            let generation = pending.entry(message_id).or_default();
+            *generation += 1;
+            *generation
+        };
+
+        tokio::time::sleep(AUTO_RENDER_DEBOUNCE).await;
+
+        let mut pending = self.pending_auto_renders.lock().unwrap();
+        if pending.get(&message_id) == Some(&generation) {
+            pending.remove(&message_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn cancel_auto_render(&self, message_id: MessageId) {
+        self.pending_auto_renders
+            .lock()
+            .unwrap()
+            .remove(&message_id);
+    }
+}
+
+impl BotContext {
+    pub fn new(
+        wolfram_alpha: WolframAlpha,
+        renderer_image: String,
+        store: Store,
+        cache_ttl: Duration,
+        queue: RenderQueue,
+    ) -> Self {
+        Self {
+            wolfram_alpha,
+            store: Arc::new(store),
+            cache_ttl,
+            pending_auto_renders: std::sync::Mutex::new(HashMap::new()),
+            queue,
+            renderer_image,
+        }
+    }
+}
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+type Context<'a> = poise::Context<'a, BotContext, Error>;
+type ApplicationContext<'a> = poise::ApplicationContext<'a, BotContext, Error>;
+
+/// Language to answer the author of a command in, see [`BotContext::locale`].
+async fn locale(ctx: Context<'_>) -> Result<Locale, Error> {
+    let locale = ctx
+        .data()
+        .locale(ctx.guild_id(), ctx.channel_id(), ctx.locale())
+        .await?;
+    Ok(locale)
+}
+
+/// Language to answer whoever used a button in, see [`BotContext::locale`].
+async fn interaction_locale(
+    data: &BotContext,
+    cmd: &ComponentInteraction,
+) -> Result<Locale, Error> {
+    let locale = data
+        .locale(cmd.guild_id, cmd.channel_id, Some(&cmd.locale))
+        .await?;
+    Ok(locale)
+}
+
+fn button_delete(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{DELETE_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Delete"))
+        .style(ButtonStyle::Danger)
+        .emoji(ReactionType::Unicode("🗑️".to_string()))
+}
+
+fn button_edit(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{EDIT_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Edit"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("✏️".to_string()))
+}
+
+fn button_source(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{SOURCE_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Source"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("📄".to_string()))
+}
+
+fn button_text(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{TEXT_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Copy as text"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("🔤".to_string()))
+}
+
+fn button_wider(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{WIDEN_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Expand"))
+        .style(ButtonStyle::Primary)
+        .emoji(ReactionType::Unicode("↔️".to_string()))
+}
+
+fn button_narrower(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{NARROW_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Narrower"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("↩️".to_string()))
+}
+
+fn button_custom_width(owner: UserId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{CUSTOM_WIDTH_CUSTOM_ID}{}", owner.get()))
+        .label(locale.tr("Width"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("📏".to_string()))
+}
+
+fn button_switch_engine(owner: UserId, engine: Engine, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{SWITCH_ENGINE_CUSTOM_ID}{}", owner.get()))
+        .label(locale.fmt("Try as {engine}", &[("engine", &engine.display_name())]))
+        .style(ButtonStyle::Primary)
+        .emoji(ReactionType::Unicode("🔀".to_string()))
+}
+
+fn button_cancel(job_id: JobId, locale: Locale) -> CreateButton {
+    CreateButton::new(format!("{CANCEL_CUSTOM_ID}{job_id}"))
+        .label(locale.tr("Cancel"))
+        .style(ButtonStyle::Secondary)
+        .emoji(ReactionType::Unicode("✖️".to_string()))
+}
+
+/// An interaction response with an embed only the user can see.
+fn ephemeral_message(title: &str, description: String) -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::default()
+            .ephemeral(true)
+            .embed(CreateEmbed::default().title(title).description(description)),
+    )
+}
+
+/// Answers with an embed only the author can see.
+async fn answer_ephemeral(
+    ctx: Context<'_>,
+    title: impl Into<String>,
+    description: String,
+) -> Result<(), Error> {
+    let mut embed = CreateEmbed::default().title(title);
+    if !description.is_empty() {
+        embed = embed.description(description);
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            // An empty component list keeps the reply callback from adding a delete button
+            .components(vec![])
+            .embed(embed),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Where macros and snippets are stored.
+#[derive(Clone, Copy)]
+enum Library {
+    User(u64),
+    Guild(u64),
+}
+
+impl Library {
+    fn scope_id(self) -> u64 {
+        match self {
+            Library::User(id) | Library::Guild(id) => id,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Library::User(_) => "your library",
+            Library::Guild(_) => "the server library",
+        }
+    }
+}
+
+/// Picks the library to change, `what` names its contents. Answers and returns `None` if the
+/// author may not change it.
+async fn library(
+    ctx: Context<'_>,
+    server: Option<bool>,
+    what: &'static str,
+) -> Result<Option<Library>, Error> {
+    if !server.unwrap_or(false) {
+        return Ok(Some(Library::User(ctx.author().id.get())));
     }
 
-    async fn register_widen_info(&self, message_id: MessageId, info: WidenInfo) {
-        self.widen_cache.lock().await.insert(message_id, info);
-    }
-}
+    let locale = locale(ctx).await?;
+    let what = locale.tr(what);
+    let Some(guild_id) = ctx.guild_id() else {
+        answer_ephemeral(
+            ctx,
+            locale.tr("Not in a server"),
+            locale.fmt(
+                "Server {what} can only be managed from within a server.",
+                &[("what", &what)],
+            ),
+        )
+        .await?;
+        return Ok(None);
+    };
+
+    let can_manage = ctx
+        .author_member()
+        .await
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+    if !can_manage {
+        answer_ephemeral(
+            ctx,
+            locale.tr("Missing permissions"),
+            locale.fmt(
+                "You need the *Manage Server* permission to change server {what}.",
+                &[("what", &what)],
+            ),
+        )
+        .await?;
+        return Ok(None);
+    }
+
+    Ok(Some(Library::Guild(guild_id.get())))
+}
+
+/// Registers the application commands in this server, or globally with `register global`. Only
+/// global commands can be used in DMs and by users who installed the app themselves.
+#[poise::command(prefix_command, owners_only)]
+pub async fn register(ctx: Context<'_>, #[flag] global: bool) -> Result<(), Error> {
+    let commands = application_commands(&ctx.framework().options().commands);
+    let count = commands.len();
+
+    if global {
+        serenity::Command::set_global_commands(ctx, commands).await?;
+        ctx.say(format!("Registered {count} commands globally."))
+            .await?;
+    } else {
+        let Some(guild_id) = ctx.guild_id() else {
+            ctx.say("Use `register global` outside of servers.").await?;
+            return Ok(());
+        };
+        guild_id.set_commands(ctx, commands).await?;
+        ctx.say(format!("Registered {count} commands in this server."))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Builds the application commands. Everything that is not guild only can also be used in DMs,
+/// group DMs and, if a user installed the app, in servers the bot is not a member of.
+fn application_commands(commands: &[poise::Command<BotContext, Error>]) -> Vec<CreateCommand> {
+    let mut builders = Vec::new();
+    for command in commands {
+        let (installs, contexts) = if command.guild_only {
+            (
+                vec![InstallationContext::Guild],
+                vec![InteractionContext::Guild],
+            )
+        } else {
+            (
+                vec![InstallationContext::Guild, InstallationContext::User],
+                vec![
+                    InteractionContext::Guild,
+                    InteractionContext::BotDm,
+                    InteractionContext::PrivateChannel,
+                ],
+            )
+        };
+
+        // poise leaves out the localized names of context menu entries
+        let context_menu = command.create_as_context_menu_command().map(|builder| {
+            command
+                .name_localizations
+                .iter()
+                .fold(builder, |builder, (locale, name)| {
+                    builder.name_localized(locale, name)
+                })
+        });
+        let created = command
+            .create_as_slash_command()
+            .into_iter()
+            .chain(context_menu);
+        for builder in created {
+            builders.push(
+                builder
+                    .integration_types(installs.clone())
+                    .contexts(contexts.clone()),
+            );
+        }
+    }
+    builders
+}
+
+#[poise::command(slash_command, prefix_command, aliases("wa", "pup"))]
+async fn wolfram(
+    ctx: Context<'_>,
+    #[description = "Show full response"] full_response: Option<bool>,
+    #[description = "Query"]
+    #[rest]
+    query: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    if full_response.unwrap_or(false) || ctx.invoked_command_name() == "wa" {
+        let result = ctx.data().wolfram_alpha.simple_query(&query).await?;
+        let images = WolframAlphaSimpleResult::group_images(result.slice_image()?, 400);
+        ctx.send({
+            let mut reply = CreateReply::default().reply(true);
+
+            // Max is 10 but be nice
+            for (idx, img) in images.iter().take(6).enumerate() {
+                let mut buffer = Vec::new();
+                img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+                    .expect("write to buffer succeeds");
+
+                reply = reply.attachment(CreateAttachment::bytes(buffer, format!("wa{idx}.png")));
+            }
+
+            reply
+        })
+        .await?
+    } else {
+        let result = ctx.data().wolfram_alpha.short_answer(&query).await?;
+        let locale = locale(ctx).await?;
+        ctx.send(
+            CreateReply::default().reply(true).embed(
+                CreateEmbed::default()
+                    .title(locale.tr("Wolfram Alpha's result"))
+                    .description(result),
+            ),
+        )
+        .await?
+    };
+
+    Ok(())
+}
+
+/// Everything we answer a render request with.
+struct RenderReply {
+    embeds: Vec<CreateEmbed>,
+    attachments: Vec<CreateAttachment>,
+    buttons: Vec<CreateButton>,
+}
+
+impl RenderReply {
+    /// Width controls are offered if `resizable` holds the current page width, see
+    /// [`resizable_width`].
+    fn new(
+        parts: &[RenderedPart],
+        owner: UserId,
+        hint: &'static str,
+        resizable: Option<f64>,
+        locale: Locale,
+    ) -> Self {
+        let mut embeds = Vec::new();
+        let mut attachments = Vec::new();
+
+        for (idx, part) in parts.iter().enumerate() {
+            match &part.result {
+                Ok(image) => {
+                    // Discord blurs attachments whose name starts like this
+                    let spoiler = if part.spoiler { "SPOILER_" } else { "" };
+                    let index = if parts.len() == 1 {
+                        String::new()
+                    } else {
+                        idx.to_string()
+                    };
+                    let file_name = format!(
+                        "{spoiler}{}{index}.{}",
+                        part.engine.key(),
+                        image.format.extension()
+                    );
+                    attachments.push(
+                        CreateAttachment::bytes(image.image.clone(), file_name)
+                            .description(part.alt_text.description()),
+                    );
+
+                    if image.too_tall {
+                        let mut description = locale.fmt(
+                            "This image is taller than {height}cm, it might be easier to read \
+                             when split into several parts.",
+                            &[("height", &render::MAX_HEIGHT_CM)],
+                        );
+                        if parts.len() > 1 {
+                            description = locale.fmt(
+                                "Block {block}: {description}",
+                                &[("block", &(idx + 1)), ("description", &description)],
+                            );
+                        }
+                        embeds.push(
+                            CreateEmbed::default()
+                                .title(locale.tr("Long render"))
+                                .description(description),
+                        );
+                    }
+                }
+                Err(error) => {
+                    let title = if parts.len() > 1 {
+                        locale.fmt(
+                            "Error rendering {engine} (block {block})",
+                            &[
+                                ("engine", &part.engine.display_name()),
+                                ("block", &(idx + 1)),
+                            ],
+                        )
+                    } else {
+                        locale.fmt(
+                            "Error rendering {engine}",
+                            &[("engine", &part.engine.display_name())],
+                        )
+                    };
+                    embeds.push(
+                        CreateEmbed::default()
+                            .title(title)
+                            .description(error.to_string())
+                            .footer(CreateEmbedFooter::new(locale.tr(hint))),
+                    );
+                }
+            }
+        }
+
+        let mut buttons = vec![
+            button_delete(owner, locale),
+            button_edit(owner, locale),
+            button_source(owner, locale),
+            button_text(owner, locale),
+        ];
+        // A single failed part might have been written for the other engine
+        if let [part] = parts {
+            if part.result.is_err() {
+                buttons.push(button_switch_engine(owner, part.engine.other(), locale));
+            }
+        }
+        if let Some(width_cm) = resizable {
+            if render::can_widen(parts) && ImageWidth::wider_than(width_cm).is_some() {
+                buttons.push(button_wider(owner, locale));
+            }
+            if ImageWidth::narrower_than(width_cm).is_some() {
+                buttons.push(button_narrower(owner, locale));
+            }
+            buttons.push(button_custom_width(owner, locale));
+        }
+
+        Self {
+            embeds,
+            attachments,
+            buttons,
+        }
+    }
+
+    /// Discord allows at most five buttons per row.
+    fn action_rows(buttons: Vec<CreateButton>) -> Vec<CreateActionRow> {
+        buttons
+            .chunks(5)
+            .map(|row| CreateActionRow::Buttons(row.to_vec()))
+            .collect()
+    }
+
+    fn into_create_message(self) -> CreateMessage {
+        CreateMessage::default()
+            .components(Self::action_rows(self.buttons))
+            .embeds(self.embeds)
+            .add_files(self.attachments)
+    }
+
+    fn into_edit_message(self) -> EditMessage {
+        // Since we don't use EditAttachments::keep_all, all previous attachments are deleted.
+        let attachments = self
+            .attachments
+            .into_iter()
+            .fold(EditAttachments::default(), EditAttachments::add);
+
+        EditMessage::default()
+            .embeds(self.embeds)
+            .attachments(attachments)
+            .components(Self::action_rows(self.buttons))
+    }
+
+    fn into_edit_interaction_response(self) -> EditInteractionResponse {
+        // Like in into_edit_message, previous attachments are deleted.
+        let attachments = self
+            .attachments
+            .into_iter()
+            .fold(EditAttachments::default(), EditAttachments::add);
+
+        EditInteractionResponse::default()
+            .components(Self::action_rows(self.buttons))
+            .embeds(self.embeds)
+            .attachments(attachments)
+    }
+
+    fn into_create_followup(self) -> CreateInteractionResponseFollowup {
+        CreateInteractionResponseFollowup::default()
+            .components(Self::action_rows(self.buttons))
+            .embeds(self.embeds)
+            .add_files(self.attachments)
+    }
+}
+
+/// Runs a render once the render queue has a free slot. While it waits, the deferred
+/// interaction response with the given token shows the queue position and a cancel button.
+///
+/// Returns `None` if the render was cancelled.
+async fn render_queued<F: Future>(
+    http: &serenity::Http,
+    data: &BotContext,
+    owner: UserId,
+    token: Option<&str>,
+    locale: Locale,
+    render: F,
+) -> Result<Option<F::Output>, Error> {
+    let mut job = data.queue.enter(owner);
+    let mut shown = None;
+
+    loop {
+        let status = job.status();
+        // Renders starting right away keep the "thinking" state of the deferred response
+        let worth_showing = matches!(status, Status::Waiting(_)) || shown.is_some();
+        if let (Some(token), true) = (token, worth_showing && shown != Some(status)) {
+            queue_status_response(status, job.id(), locale)
+                .execute(http, token)
+                .await?;
+            shown = Some(status);
+        }
+
+        match status {
+            Status::Waiting(_) => job.changed().await,
+            Status::Running => break,
+            Status::Cancelled => return Ok(None),
+        }
+    }
+
+    Ok(job.run(render).await)
+}
+
+fn queue_status_response(status: Status, job_id: JobId, locale: Locale) -> EditInteractionResponse {
+    let description = match status {
+        Status::Waiting(position) => locale.fmt(
+            "Waiting for a free renderer, you are number {position} in the queue.",
+            &[("position", &position)],
+        ),
+        Status::Running | Status::Cancelled => locale.tr("Rendering...").to_string(),
+    };
+
+    EditInteractionResponse::default()
+        .embed(CreateEmbed::default().description(description))
+        .components(vec![CreateActionRow::Buttons(vec![button_cancel(
+            job_id, locale,
+        )])])
+}
+
+fn cancelled_response(locale: Locale) -> EditInteractionResponse {
+    EditInteractionResponse::default()
+        .embed(CreateEmbed::default().title(locale.tr("Render cancelled")))
+        .components(vec![])
+}
+
+/// Builders for the buttons of a message, to put them back after it showed others for a while.
+fn recreate_buttons(message: &Message) -> Vec<CreateActionRow> {
+    message
+        .components
+        .iter()
+        .map(|row| {
+            let buttons = row
+                .components
+                .iter()
+                .filter_map(|component| {
+                    let ActionRowComponent::Button(button) = component else {
+                        return None;
+                    };
+                    let ButtonKind::NonLink { custom_id, style } = &button.data else {
+                        return None;
+                    };
+                    let mut recreated = CreateButton::new(custom_id)
+                        .style(*style)
+                        .disabled(button.disabled);
+                    if let Some(label) = &button.label {
+                        recreated = recreated.label(label);
+                    }
+                    if let Some(emoji) = &button.emoji {
+                        recreated = recreated.emoji(emoji.clone());
+                    }
+                    Some(recreated)
+                })
+                .collect();
+            CreateActionRow::Buttons(buttons)
+        })
+        .collect()
+}
+
+/// Responses offer to change their page width once they did not fit or were resized before.
+/// Returns the page width to resize from in that case.
+fn resizable_width(parts: &[RenderedPart], width_cm: f64, resized: bool) -> Option<f64> {
+    (resized || render::can_widen(parts)).then_some(width_cm)
+}
+
+/// Adds a render to the history of its owner, unless no part of it rendered.
+async fn record_history(
+    data: &BotContext,
+    parts: &[RenderedPart],
+    info: &WidenInfo,
+    guild_id: Option<GuildId>,
+    response: &Message,
+) -> Result<(), Error> {
+    let Some(image) = parts.iter().find_map(|part| part.result.as_ref().ok()) else {
+        return Ok(());
+    };
+    let entry = HistoryEntry {
+        owner: info.owner,
+        engine: info.engine,
+        source: info.source.clone(),
+        guild_id,
+        channel_id: response.channel_id,
+        response_id: response.id,
+        thumbnail: history::thumbnail(&image.image),
+        request: info.request,
+    };
+    data.store.record_history(&entry).await?;
+    Ok(())
+}
+
+#[poise::command(context_menu_command = "Render math")]
+async fn math_context_menu(ctx: ApplicationContext<'_>, message: Message) -> Result<(), Error> {
+    render_message(ctx, message).await
+}
+
+/// Renders a message with the engine it looks like it was written for.
+async fn render_message(ctx: ApplicationContext<'_>, message: Message) -> Result<(), Error> {
+    if let Some(response_id) = ctx.data().rendered_response_id(message.id).await? {
+        // try to delete, if it is already gone that's fine too
+        let _ = ctx
+            .http()
+            .delete_message(message.channel_id, response_id, None)
+            .await;
+    }
+
+    ctx.defer().await?;
+
+    let settings = ctx
+        .data()
+        .settings(ctx.guild_id(), ctx.channel_id())
+        .await?;
+    let (source, spoiler) = preprocess::strip_spoilers(&message.content);
+    let engine = preprocess::detect_engine(&source).unwrap_or(settings.default_engine);
+    let libraries = ctx
+        .data()
+        .libraries(ctx.guild_id(), ctx.author().id)
+        .await?;
+    let appearance = ctx.data().appearance(ctx.author().id).await?;
+    let request = RenderRequest {
+        spoiler,
+        ..RenderRequest::new(appearance, settings.default_width.centimetres())
+    };
+    let locale = locale(ctx.into()).await?;
+    let parts = render_queued(
+        ctx.http(),
+        ctx.data(),
+        ctx.author().id,
+        Some(&ctx.interaction.token),
+        locale,
+        render::render_input(
+            ctx.id(),
+            &ctx.data().renderer_image,
+            engine,
+            &source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        ctx.interaction
+            .edit_response(ctx, cancelled_response(locale))
+            .await?;
+        return Ok(());
+    };
+    let resizable = resizable_width(&parts, settings.default_width.centimetres(), false);
+
+    let response = ctx
+        .interaction
+        .edit_response(
+            ctx,
+            RenderReply::new(
+                &parts,
+                ctx.author().id,
+                "You can edit your message and try again.",
+                resizable,
+                locale,
+            )
+            .into_edit_interaction_response(),
+        )
+        .await?;
+
+    let rendered = RenderedResponse {
+        response_id: response.id,
+        channel_id: message.channel_id,
+        engine,
+        owner: ctx.author().id,
+    };
+    ctx.data()
+        .register_rendered_response(message.id, rendered)
+        .await?;
+
+    let info = WidenInfo {
+        owner: ctx.author().id,
+        engine,
+        source,
+        request,
+    };
+    record_history(ctx.data(), &parts, &info, ctx.guild_id(), &response).await?;
+    ctx.data().register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+/// Render LaTeX code entered in a text box
+#[poise::command(slash_command)]
+async fn tex(
+    ctx: ApplicationContext<'_>,
+    #[description = "Colors of the image, defaults to your /theme"] theme: Option<Theme>,
+    #[description = "Leave out the background"] transparent: Option<bool>,
+) -> Result<(), Error> {
+    let request = default_request(ctx, theme, transparent).await?;
+    render_from_modal(ctx, Some(Engine::Latex), request).await
+}
+
+/// Render typst code entered in a text box
+#[poise::command(slash_command)]
+async fn typst(
+    ctx: ApplicationContext<'_>,
+    #[description = "Colors of the image, defaults to your /theme"] theme: Option<Theme>,
+    #[description = "Leave out the background"] transparent: Option<bool>,
+) -> Result<(), Error> {
+    let request = default_request(ctx, theme, transparent).await?;
+    render_from_modal(ctx, Some(Engine::Typst), request).await
+}
+
+/// Render LaTeX or typst code entered in a text box, picking every detail of the image
+#[poise::command(slash_command)]
+#[allow(clippy::too_many_arguments)]
+async fn render(
+    ctx: ApplicationContext<'_>,
+    #[description = "Engine, guessed from the code if empty"] engine: Option<Engine>,
+    #[description = "Page width in centimetres, defaults to the one of this channel"]
+    #[min = 3]
+    #[max = 50]
+    width: Option<f64>,
+    #[description = "Colors of the image, defaults to your /theme"] theme: Option<Theme>,
+    #[description = "Resolution in dots per inch, defaults to 300"]
+    #[min = 72]
+    #[max = 600]
+    dpi: Option<u32>,
+    #[description = "File format of the image, defaults to PNG"] format: Option<OutputFormat>,
+    #[description = "Leave out the background"] transparent: Option<bool>,
+    #[description = "Hide the image behind a spoiler"] spoiler: Option<bool>,
+) -> Result<(), Error> {
+    let defaults = default_request(ctx, theme, transparent).await?;
+    let request = RenderRequest {
+        width_cm: width.unwrap_or(defaults.width_cm),
+        dpi: dpi.unwrap_or(defaults.dpi),
+        format: format.unwrap_or(defaults.format),
+        spoiler: spoiler.unwrap_or(defaults.spoiler),
+        ..defaults
+    };
+    render_from_modal(ctx, engine, request).await
+}
+
+/// The request of a render in the channel of a command, with the user's appearance unless
+/// overridden.
+async fn default_request(
+    ctx: ApplicationContext<'_>,
+    theme: Option<Theme>,
+    transparent: Option<bool>,
+) -> Result<RenderRequest, Error> {
+    let settings = ctx
+        .data()
+        .settings(ctx.guild_id(), ctx.channel_id())
+        .await?;
+    let default_appearance = ctx.data().appearance(ctx.author().id).await?;
+    let appearance = Appearance {
+        theme: theme.unwrap_or(default_appearance.theme),
+        transparent: transparent.unwrap_or(default_appearance.transparent),
+    };
+    Ok(RenderRequest::new(
+        appearance,
+        settings.default_width.centimetres(),
+    ))
+}
+
+/// Discord does not allow longer text inputs.
+const MAX_MODAL_SOURCE_LENGTH: usize = 4000;
+
+/// A modal asking for source code, prefilled with `source` unless it is empty. Without an
+/// engine the code may be written for either.
+fn source_modal(
+    engine: Option<Engine>,
+    custom_id: &str,
+    title: &str,
+    source: &str,
+) -> CreateInteractionResponse {
+    let label = engine.map_or("LaTeX / typst", Engine::display_name);
+    let mut input = CreateInputText::new(InputTextStyle::Paragraph, label, "source")
+        .min_length(1)
+        .max_length(MAX_MODAL_SOURCE_LENGTH as u16);
+    if !source.is_empty() {
+        input = input.value(source);
+    }
+
+    CreateInteractionResponse::Modal(
+        CreateModal::new(custom_id, title).components(vec![CreateActionRow::InputText(input)]),
+    )
+}
+
+fn modal_text(data: &ModalInteractionData, custom_id: &str) -> Option<String> {
+    data.components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(text) if text.custom_id == custom_id => {
+                text.value.clone()
+            }
+            _ => None,
+        })
+}
+
+/// Asks for source code in a modal and renders it. Without an engine it is guessed from the
+/// code, falling back to the default engine of the channel.
+async fn render_from_modal(
+    ctx: ApplicationContext<'_>,
+    engine: Option<Engine>,
+    request: RenderRequest,
+) -> Result<(), Error> {
+    let locale = locale(ctx.into()).await?;
+    let title = match engine {
+        Some(engine) => locale.fmt("Render {engine}", &[("engine", &engine.display_name())]),
+        None => locale.tr("Render math").to_string(),
+    };
+    let custom_id = ctx.interaction.id.to_string();
+    ctx.interaction
+        .create_response(ctx, source_modal(engine, &custom_id, &title, ""))
+        .await?;
+    ctx.has_sent_initial_response.store(true, Ordering::SeqCst);
+
+    let submission = ModalInteractionCollector::new(ctx)
+        .filter(move |modal| modal.data.custom_id == custom_id)
+        .timeout(Duration::from_secs(60 * 60))
+        .await;
+    let Some(submission) = submission else {
+        return Ok(());
+    };
+    let Some(source) = modal_text(&submission.data, "source") else {
+        return Ok(());
+    };
+
+    // Shows a "thinking" state until we edit the response
+    submission
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::default()),
+        )
+        .await?;
+
+    let settings = ctx
+        .data()
+        .settings(ctx.guild_id(), ctx.channel_id())
+        .await?;
+    let engine = engine
+        .or_else(|| preprocess::detect_engine(&source))
+        .unwrap_or(settings.default_engine);
+    let libraries = ctx
+        .data()
+        .libraries(ctx.guild_id(), ctx.author().id)
+        .await?;
+    let parts = render_queued(
+        ctx.http(),
+        ctx.data(),
+        ctx.author().id,
+        Some(&submission.token),
+        locale,
+        render::render_input(
+            submission.id.get(),
+            &ctx.data().renderer_image,
+            engine,
+            &source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        submission
+            .edit_response(ctx, cancelled_response(locale))
+            .await?;
+        return Ok(());
+    };
+    let resized = request.width_cm != settings.default_width.centimetres();
+    let resizable = resizable_width(&parts, request.width_cm, resized);
+
+    let response = submission
+        .edit_response(
+            ctx,
+            RenderReply::new(
+                &parts,
+                ctx.author().id,
+                "You can run the command again.",
+                resizable,
+                locale,
+            )
+            .into_edit_interaction_response(),
+        )
+        .await?;
+
+    let info = WidenInfo {
+        owner: ctx.author().id,
+        engine,
+        source,
+        request,
+    };
+    record_history(ctx.data(), &parts, &info, ctx.guild_id(), &response).await?;
+    ctx.data().register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+async fn handle_event<'a>(
+    ctx: &'a serenity::Context,
+    event: &'a FullEvent,
+    _framework: poise::FrameworkContext<'a, BotContext, Error>,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    match event {
+        FullEvent::InteractionCreate { interaction } => {
+            if let Some(cmd) = interaction.as_message_component() {
+                trace!("Got interaction from '{}' ({})", cmd.user.name, cmd.user.id);
+                if cmd.data.custom_id.starts_with(DELETE_CUSTOM_ID) {
+                    handle_delete_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(WIDEN_CUSTOM_ID) {
+                    handle_resize_button_click(ctx, cmd, data, Resize::Wider).await?;
+                } else if cmd.data.custom_id.starts_with(NARROW_CUSTOM_ID) {
+                    handle_resize_button_click(ctx, cmd, data, Resize::Narrower).await?;
+                } else if cmd.data.custom_id.starts_with(CUSTOM_WIDTH_CUSTOM_ID) {
+                    handle_custom_width_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(EDIT_CUSTOM_ID) {
+                    handle_edit_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(SOURCE_CUSTOM_ID) {
+                    handle_source_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(TEXT_CUSTOM_ID) {
+                    handle_text_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(CANCEL_CUSTOM_ID) {
+                    handle_cancel_button_click(ctx, cmd, data).await?;
+                } else if cmd.data.custom_id.starts_with(SWITCH_ENGINE_CUSTOM_ID) {
+                    handle_switch_engine_button_click(ctx, cmd, data).await?;
+                }
+            }
+        }
+        FullEvent::Message { new_message } => {
+            handle_mention(ctx, new_message, data).await?;
+            handle_auto_render(
+                ctx,
+                new_message.guild_id,
+                new_message.channel_id,
+                new_message.id,
+                &new_message.author,
+                &new_message.content,
+                data,
+            )
+            .await?;
+        }
+        FullEvent::MessageUpdate { event, .. } => {
+            // Content is only present if it changed
+            if let Some(content) = &event.content {
+                handle_message_edit(ctx, event.guild_id, event.id, content, data).await?;
+
+                // The edit might have added math to a message we did not render yet
+                if let Some(author) = &event.author {
+                    handle_auto_render(
+                        ctx,
+                        event.guild_id,
+                        event.channel_id,
+                        event.id,
+                        author,
+                        content,
+                        data,
+                    )
+                    .await?;
+                }
+            }
+        }
+        FullEvent::ReactionAdd { add_reaction } => {
+            handle_reaction(ctx, add_reaction, data).await?;
+        }
+        FullEvent::MessageDelete {
+            deleted_message_id, ..
+        } => {
+            data.cancel_auto_render(*deleted_message_id);
+            handle_message_delete(ctx, *deleted_message_id, data).await?;
+        }
+        FullEvent::MessageDeleteBulk {
+            multiple_deleted_messages_ids,
+            ..
+        } => {
+            for message_id in multiple_deleted_messages_ids {
+                data.cancel_auto_render(*message_id);
+                handle_message_delete(ctx, *message_id, data).await?;
+            }
+        }
+        _ => {}
+    };
+    Ok(())
+}
+
+/// Renders an edited message again and replaces the content of our existing response.
+async fn handle_message_edit<'a>(
+    ctx: &'a serenity::Context,
+    guild_id: Option<GuildId>,
+    message_id: MessageId,
+    content: &'a str,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(rendered) = data.rendered_response(message_id).await? else {
+        return Ok(());
+    };
+
+    info!("Re-rendering edited message {message_id}");
+
+    let settings = data.settings(guild_id, rendered.channel_id).await?;
+    let libraries = data.libraries(guild_id, rendered.owner).await?;
+    // The response keeps looking the way it was requested, at the width it was resized to
+    let request = match data.widen_info(rendered.response_id).await? {
+        Some(info) => info.request,
+        None => {
+            let appearance = data.appearance(rendered.owner).await?;
+            RenderRequest::new(appearance, settings.default_width.centimetres())
+        }
+    };
+    // but follows spoilers being added or removed
+    let (source, spoiler) = preprocess::strip_spoilers(content);
+    let request = RenderRequest { spoiler, ..request };
+    let locale = data.locale(guild_id, rendered.channel_id, None).await?;
+    let parts = render_queued(
+        &ctx.http,
+        data,
+        rendered.owner,
+        None,
+        locale,
+        render::render_input(
+            next_event_render_id(),
+            &data.renderer_image,
+            rendered.engine,
+            &source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        return Ok(());
+    };
+    let resized = request.width_cm != settings.default_width.centimetres();
+    let resizable = resizable_width(&parts, request.width_cm, resized);
+
+    let edited = rendered
+        .channel_id
+        .edit_message(
+            ctx,
+            rendered.response_id,
+            RenderReply::new(
+                &parts,
+                rendered.owner,
+                "You can edit your message and try again.",
+                resizable,
+                locale,
+            )
+            .into_edit_message(),
+        )
+        .await;
+
+    let edited = match edited {
+        Ok(edited) => edited,
+        Err(e) => {
+            // Our response was most likely deleted, there is nothing to update anymore
+            info!("Could not update response to edited message {message_id}: {e}");
+            data.forget_rendered_response(message_id).await?;
+            return Ok(());
+        }
+    };
+
+    let info = WidenInfo {
+        owner: rendered.owner,
+        engine: rendered.engine,
+        source,
+        request,
+    };
+    record_history(data, &parts, &info, guild_id, &edited).await?;
+    data.register_widen_info(rendered.response_id, info).await?;
+    Ok(())
+}
+
+/// Renders math in messages sent to channels with automatic rendering enabled. Disabling
+/// commands does not affect it, `/config autorender` does.
+async fn handle_auto_render<'a>(
+    ctx: &'a serenity::Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    author: &'a User,
+    content: &'a str,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    if author.bot {
+        return Ok(());
+    }
+
+    let settings = data.settings(guild_id, channel_id).await?;
+    let engine = settings.default_engine;
+    let (source, spoiler) = preprocess::strip_spoilers(content);
+    let has_math =
+        preprocess::contains_math(&source, engine) || !preprocess::code_blocks(&source).is_empty();
+    if !settings.auto_render || !has_math {
+        return Ok(());
+    }
+
+    if !data.debounce_auto_render(message_id).await {
+        // A later edit or delete superseded this one
+        return Ok(());
+    }
+    if data.rendered_response_id(message_id).await?.is_some() {
+        // Somebody rendered it explicitly while we were waiting
+        return Ok(());
+    }
+
+    info!("Auto-rendering message {message_id} from '{}'", author.name);
+
+    let libraries = data.libraries(guild_id, author.id).await?;
+    let appearance = data.appearance(author.id).await?;
+    let request = RenderRequest {
+        spoiler,
+        ..RenderRequest::new(appearance, settings.default_width.centimetres())
+    };
+    let locale = data.locale(guild_id, channel_id, None).await?;
+    let parts = render_queued(
+        &ctx.http,
+        data,
+        author.id,
+        None,
+        locale,
+        render::render_input(
+            next_event_render_id(),
+            &data.renderer_image,
+            engine,
+            &source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        return Ok(());
+    };
+    let resizable = resizable_width(&parts, settings.default_width.centimetres(), false);
+
+    if parts.iter().all(|part| part.result.is_err()) {
+        // Not everything with dollar signs is math, so failures stay quiet
+        info!("Not auto-rendering message {message_id}, it did not render");
+        return Ok(());
+    }
+
+    let response = channel_id
+        .send_message(
+            ctx,
+            RenderReply::new(
+                &parts,
+                author.id,
+                "You can edit your message and try again.",
+                resizable,
+                locale,
+            )
+            .into_create_message()
+            .reference_message((channel_id, message_id))
+            .allowed_mentions(CreateAllowedMentions::new().replied_user(false)),
+        )
+        .await?;
+
+    let rendered = RenderedResponse {
+        response_id: response.id,
+        channel_id,
+        engine,
+        owner: author.id,
+    };
+    data.register_rendered_response(message_id, rendered)
+        .await?;
+
+    let info = WidenInfo {
+        owner: author.id,
+        engine,
+        source,
+        request,
+    };
+    record_history(data, &parts, &info, guild_id, &response).await?;
+    data.register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+/// Renders a message somebody reacted to with the configured emoji.
+async fn handle_reaction<'a>(
+    ctx: &'a serenity::Context,
+    reaction: &'a Reaction,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(user_id) = reaction.user_id else {
+        return Ok(());
+    };
+    if user_id == ctx.cache.current_user().id {
+        return Ok(());
+    }
+
+    let settings = data
+        .settings(reaction.guild_id, reaction.channel_id)
+        .await?;
+    let Some(emoji) = &settings.render_reaction else {
+        return Ok(());
+    };
+    if !same_emoji(emoji, &reaction.emoji) {
+        return Ok(());
+    }
+
+    let message = reaction.message(ctx).await?;
+    // Messages of bots, including the renders themselves, are not sources
+    if message.author.bot {
+        return Ok(());
+    }
+    info!(
+        "Rendering message {} on a reaction from {user_id}",
+        message.id
+    );
+    render_triggered(ctx, &message, reaction.guild_id, user_id, data).await
+}
+
+/// Whether two emoji are the same, ignoring how a client happened to encode a unicode emoji.
+fn same_emoji(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        (ReactionType::Unicode(a), ReactionType::Unicode(b)) => {
+            a.replace('\u{FE0F}', "") == b.replace('\u{FE0F}', "")
+        }
+        _ => false,
+    }
+}
+
+/// Renders the parent of a reply that consists of nothing but a mention of the bot. Replies
+/// with more text are left to the prefix commands.
+async fn handle_mention<'a>(
+    ctx: &'a serenity::Context,
+    message: &'a Message,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(parent) = message.referenced_message.as_deref() else {
+        return Ok(());
+    };
+    let bot_id = ctx.cache.current_user().id;
+    if message.author.bot || parent.author.id == bot_id {
+        return Ok(());
+    }
+
+    let content = message.content.trim();
+    if content != format!("<@{bot_id}>") && content != format!("<@!{bot_id}>") {
+        return Ok(());
+    }
+
+    info!(
+        "Rendering message {} on a mention from '{}'",
+        parent.id, message.author.name
+    );
+    render_triggered(ctx, parent, message.guild_id, message.author.id, data).await
+}
+
+/// Renders a message on behalf of somebody who asked for it without an interaction, replacing
+/// an earlier response to the same message.
+async fn render_triggered<'a>(
+    ctx: &'a serenity::Context,
+    message: &'a Message,
+    guild_id: Option<GuildId>,
+    requester: UserId,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    // Reactions and mentions do what the context menu does, so they go away with it
+    if !data
+        .command_enabled(guild_id, message.channel_id, RENDER_MESSAGE_COMMAND)
+        .await?
+    {
+        return Ok(());
+    }
+
+    if let Some(response_id) = data.rendered_response_id(message.id).await? {
+        // try to delete, if it is already gone that's fine too
+        let _ = message.channel_id.delete_message(ctx, response_id).await;
+        data.forget_rendered_response(message.id).await?;
+    }
+
+    let settings = data.settings(guild_id, message.channel_id).await?;
+    let (source, spoiler) = preprocess::strip_spoilers(&message.content);
+    let engine = preprocess::detect_engine(&source).unwrap_or(settings.default_engine);
+    let libraries = data.libraries(guild_id, requester).await?;
+    let appearance = data.appearance(requester).await?;
+    let request = RenderRequest {
+        spoiler,
+        ..RenderRequest::new(appearance, settings.default_width.centimetres())
+    };
+    let locale = data.locale(guild_id, message.channel_id, None).await?;
+    let parts = render_queued(
+        &ctx.http,
+        data,
+        requester,
+        None,
+        locale,
+        render::render_input(
+            next_event_render_id(),
+            &data.renderer_image,
+            engine,
+            &source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        return Ok(());
+    };
+    let resizable = resizable_width(&parts, settings.default_width.centimetres(), false);
+
+    let response = message
+        .channel_id
+        .send_message(
+            ctx,
+            RenderReply::new(
+                &parts,
+                requester,
+                "You can edit your message and try again.",
+                resizable,
+                locale,
+            )
+            .into_create_message()
+            .reference_message(message)
+            .allowed_mentions(CreateAllowedMentions::new().replied_user(false)),
+        )
+        .await?;
+
+    let rendered = RenderedResponse {
+        response_id: response.id,
+        channel_id: message.channel_id,
+        engine,
+        owner: requester,
+    };
+    data.register_rendered_response(message.id, rendered)
+        .await?;
+
+    let info = WidenInfo {
+        owner: requester,
+        engine,
+        source,
+        request,
+    };
+    record_history(data, &parts, &info, guild_id, &response).await?;
+    data.register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+/// Deletes our response to a message that was just deleted.
+async fn handle_message_delete<'a>(
+    ctx: &'a serenity::Context,
+    message_id: MessageId,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(rendered) = data.rendered_response(message_id).await? else {
+        return Ok(());
+    };
+
+    info!("Deleting response to deleted message {message_id}");
 
-impl BotContext {
-    pub fn new(wolfram_alpha: WolframAlpha, renderer_image: String) -> Self {
-        Self {
-            wolfram_alpha,
-            rendered_cache: Arc::new(Mutex::new(HashMap::new())),
-            widen_cache: Arc::new(Mutex::new(HashMap::new())),
-            renderer_image,
-        }
-    }
-}
+    // try to delete, if it is already gone that's fine too
+    let _ = rendered
+        .channel_id
+        .delete_message(ctx, rendered.response_id)
+        .await;
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
-type Context<'a> = poise::Context<'a, BotContext, Error>;
+    data.forget_rendered_response(message_id).await?;
+    data.forget_response(rendered.response_id).await?;
 
-fn button_delete(owner: UserId) -> CreateButton {
-    CreateButton::new(format!("{DELETE_CUSTOM_ID}{}", owner.get()))
-        .label("Delete")
-        .style(ButtonStyle::Danger)
-        .emoji(ReactionType::Unicode("🗑️".to_string()))
+    Ok(())
 }
 
-fn button_wider(owner: UserId) -> CreateButton {
-    CreateButton::new(format!("{WIDEN_CUSTOM_ID}{}", owner.get()))
-        .label("Expand")
-        .style(ButtonStyle::Primary)
-        .emoji(ReactionType::Unicode("↔️".to_string()))
+/// Unique id for renders that are not triggered by an interaction, used to name the runner.
+fn next_event_render_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
 }
 
-#[poise::command(prefix_command)]
-pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
-    poise::builtins::register_application_commands_buttons(ctx).await?;
-    Ok(())
+#[derive(Debug, Clone, Copy)]
+enum Resize {
+    Wider,
+    Narrower,
 }
 
-#[poise::command(slash_command, prefix_command, aliases("wa", "pup"))]
-async fn wolfram(
-    ctx: Context<'_>,
-    #[description = "Show full response"] full_response: Option<bool>,
-    #[description = "Query"]
-    #[rest]
-    query: String,
+async fn handle_resize_button_click<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+    resize: Resize,
 ) -> Result<(), Error> {
-    ctx.defer().await?;
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
+        return Ok(());
+    };
 
-    if full_response.unwrap_or(false) || ctx.invoked_command_name() == "wa" {
-        let result = ctx.data().wolfram_alpha.simple_query(&query).await?;
-        let images = WolframAlphaSimpleResult::group_images(result.slice_image()?, 400);
-        ctx.send({
-            let mut reply = CreateReply::default().reply(true);
+    if !may_act_on(ctx, cmd, data, info.owner, "resize").await? {
+        return Ok(());
+    }
 
-            // Max is 10 but be nice
-            for (idx, img) in images.iter().take(6).enumerate() {
-                let mut buffer = Vec::new();
-                img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
-                    .expect("write to buffer succeeds");
+    let target = match resize {
+        Resize::Wider => ImageWidth::wider_than(info.request.width_cm),
+        Resize::Narrower => ImageWidth::narrower_than(info.request.width_cm),
+    };
+    let Some(target) = target else {
+        // The buttons are only shown if there is a size to go to
+        return answer_unknown_button(ctx, cmd, data).await;
+    };
 
-                reply = reply.attachment(CreateAttachment::bytes(buffer, format!("wa{idx}.png")));
-            }
+    cmd.defer(ctx).await?;
 
-            reply
-        })
-        .await?
-    } else {
-        let result = ctx.data().wolfram_alpha.short_answer(&query).await?;
-        ctx.send(
-            CreateReply::default().reply(true).embed(
-                CreateEmbed::default()
-                    .title("Wolfram Alpha's result")
-                    .description(result),
-            ),
-        )
-        .await?
+    let info = WidenInfo {
+        request: RenderRequest {
+            width_cm: target.centimetres(),
+            ..info.request
+        },
+        ..info
     };
-
-    Ok(())
+    rerender_response(ctx, data, cmd, &cmd.token, info, cmd.id.get()).await
 }
 
-#[poise::command(context_menu_command = "Render LaTeX")]
-async fn tex_context_menu(ctx: Context<'_>, message: Message) -> Result<(), Error> {
-    if let Some(response_id) = ctx.data().rendered_response_id(message.id).await {
-        // try to delete, if it is already gone that's fine too
-        let _ = ctx
-            .http()
-            .delete_message(message.channel_id, response_id, None)
-            .await;
-    }
-
-    ctx.defer().await?;
+fn width_modal(custom_id: &str, width_cm: f64, locale: Locale) -> CreateInteractionResponse {
+    let label = locale.tr("Width in centimetres");
+    let input = CreateInputText::new(InputTextStyle::Short, label, "width")
+        .placeholder(locale.fmt(
+            "{min} to {max}",
+            &[
+                ("min", &render::MIN_WIDTH_CM),
+                ("max", &render::MAX_WIDTH_CM),
+            ],
+        ))
+        .value(width_cm.to_string())
+        .max_length(8);
 
-    let image = latex::render_latex(
-        ctx.id(),
-        ctx.data().renderer_image.clone(),
-        message.content.clone(),
-        ImageWidth::Normal,
+    CreateInteractionResponse::Modal(
+        CreateModal::new(custom_id, locale.tr("Change width"))
+            .components(vec![CreateActionRow::InputText(input)]),
     )
-    .await;
-
-    let image = match image {
-        Ok(image) => image,
-        Err(error) => {
-            let handle = ctx
-                .send(
-                    CreateReply::default().embed(
-                        CreateEmbed::default()
-                            .title("Error rendering LaTeX")
-                            .title("You can edit your message and try again.")
-                            .description(error.to_string()),
-                    ),
-                )
-                .await?;
-
-            let response = handle.message().await?;
+}
 
-            ctx.data()
-                .register_rendered_response_id(message.id, response.id)
-                .await;
+/// Parses a width like `14`, `14.5cm` or `14,5`, returning `None` if it is out of range.
+fn parse_width(text: &str) -> Option<f64> {
+    let text = text.trim().trim_end_matches("cm").trim().replace(',', ".");
+    text.parse::<f64>()
+        .ok()
+        .filter(|width| (render::MIN_WIDTH_CM..=render::MAX_WIDTH_CM).contains(width))
+}
 
-            return Ok(());
-        }
+async fn handle_custom_width_button_click<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
+        return Ok(());
     };
 
-    let handle = ctx
-        .send({
-            let mut reply =
-                CreateReply::default().attachment(CreateAttachment::bytes(image.png, "latex.png"));
-
-            if image.overrun_hbox {
-                reply = reply.components(vec![CreateActionRow::Buttons(vec![
-                    button_delete(ctx.author().id),
-                    button_wider(ctx.author().id),
-                ])]);
-            }
+    if !may_act_on(ctx, cmd, data, info.owner, "resize").await? {
+        return Ok(());
+    }
 
-            reply
-        })
+    let locale = interaction_locale(data, cmd).await?;
+    let custom_id = cmd.id.to_string();
+    cmd.create_response(ctx, width_modal(&custom_id, info.request.width_cm, locale))
         .await?;
 
-    let response = handle.message().await?;
-
-    ctx.data()
-        .register_rendered_response_id(message.id, response.id)
+    let submission = ModalInteractionCollector::new(ctx)
+        .filter(move |modal| modal.data.custom_id == custom_id)
+        .timeout(Duration::from_secs(10 * 60))
         .await;
+    let Some(submission) = submission else {
+        return Ok(());
+    };
 
-    if image.overrun_hbox {
-        let info = WidenInfo {
-            owner: ctx.author().id,
-            latex: message.content,
-        };
-        ctx.data().register_widen_info(message.id, info).await;
-    }
+    let width = modal_text(&submission.data, "width").and_then(|text| parse_width(&text));
+    let Some(width_cm) = width else {
+        let description = locale.fmt(
+            "Please enter a width between {min} and {max} centimetres.",
+            &[
+                ("min", &render::MIN_WIDTH_CM),
+                ("max", &render::MAX_WIDTH_CM),
+            ],
+        );
+        submission
+            .create_response(
+                ctx,
+                ephemeral_message(locale.tr("Invalid width"), description),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    submission
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let info = WidenInfo {
+        request: RenderRequest {
+            width_cm,
+            ..info.request
+        },
+        ..info
+    };
+    rerender_response(ctx, data, cmd, &submission.token, info, submission.id.get()).await
+}
+
+/// Renders the source of the response a button belongs to again, possibly changed, and replaces
+/// the response through the interaction with the given token. Going through the interaction also
+/// works in channels the bot cannot see, like DMs of users who installed the app.
+async fn rerender_response(
+    ctx: &serenity::Context,
+    data: &BotContext,
+    cmd: &ComponentInteraction,
+    token: &str,
+    info: WidenInfo,
+    context_id: u64,
+) -> Result<(), Error> {
+    let response_id = cmd.message.id;
+    info!(
+        "Re-rendering response {response_id} at {}cm",
+        info.request.width_cm
+    );
+
+    let settings = data.settings(cmd.guild_id, cmd.channel_id).await?;
+    let libraries = data.libraries(cmd.guild_id, info.owner).await?;
+    let locale = interaction_locale(data, cmd).await?;
+    let parts = render_queued(
+        &ctx.http,
+        data,
+        info.owner,
+        Some(token),
+        locale,
+        render::render_input(
+            context_id,
+            &data.renderer_image,
+            info.engine,
+            &info.source,
+            &libraries,
+            &info.request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        // The queue status goes away and the previous render stays
+        let embeds = cmd.message.embeds.iter().cloned().map(CreateEmbed::from);
+        EditInteractionResponse::default()
+            .embeds(embeds.collect())
+            .components(recreate_buttons(&cmd.message))
+            .execute(&ctx.http, token)
+            .await?;
+        return Ok(());
+    };
+    let resized = info.request.width_cm != settings.default_width.centimetres();
+    let resizable = resizable_width(&parts, info.request.width_cm, resized);
+
+    let response = RenderReply::new(
+        &parts,
+        info.owner,
+        "You can edit the source and try again.",
+        resizable,
+        locale,
+    )
+    .into_edit_interaction_response()
+    .execute(&ctx.http, token)
+    .await?;
 
+    record_history(data, &parts, &info, cmd.guild_id, &response).await?;
+    data.register_widen_info(response_id, info).await?;
     Ok(())
 }
 
-#[poise::command(context_menu_command = "Render typst")]
-async fn typst_context_menu(ctx: Context<'_>, message: Message) -> Result<(), Error> {
-    if let Some(response_id) = ctx.data().rendered_response_id(message.id).await {
-        // try to delete, if it is already gone that's fine too
-        let _ = ctx
-            .http()
-            .delete_message(message.channel_id, response_id, None)
-            .await;
+async fn handle_edit_button_click<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
+        return Ok(());
+    };
+
+    if info.owner != cmd.user.id {
+        return answer_action_not_allowed(ctx, cmd, data).await;
     }
 
-    ctx.defer().await?;
+    let locale = interaction_locale(data, cmd).await?;
+    if info.source.chars().count() > MAX_MODAL_SOURCE_LENGTH {
+        let description = locale.fmt(
+            "Discord only lets me prefill {length} characters, edit your original message \
+             instead.",
+            &[("length", &MAX_MODAL_SOURCE_LENGTH)],
+        );
+        cmd.create_response(
+            ctx,
+            ephemeral_message(locale.tr("Source too long"), description),
+        )
+        .await?;
+        return Ok(());
+    }
 
-    let image = crate::typst::render_typst(
-        ctx.id(),
-        ctx.data().renderer_image.clone(),
-        message.content.clone(),
+    let custom_id = cmd.id.to_string();
+    let title = locale.fmt("Edit {engine}", &[("engine", &info.engine.display_name())]);
+    cmd.create_response(
+        ctx,
+        source_modal(Some(info.engine), &custom_id, &title, &info.source),
     )
-    .await;
-
-    let image = match image {
-        Ok(image) => image,
-        Err(error) => {
-            let handle = ctx
-                .send(
-                    CreateReply::default().embed(
-                        CreateEmbed::default()
-                            .title("Error rendering typst")
-                            .title("You can edit your message and try again.")
-                            .description(error.to_string()),
-                    ),
-                )
-                .await?;
+    .await?;
+
+    let submission = ModalInteractionCollector::new(ctx)
+        .filter(move |modal| modal.data.custom_id == custom_id)
+        .timeout(Duration::from_secs(60 * 60))
+        .await;
+    let Some(submission) = submission else {
+        return Ok(());
+    };
+    let Some(source) = modal_text(&submission.data, "source") else {
+        return Ok(());
+    };
 
-            let response = handle.message().await?;
+    submission
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
 
-            ctx.data()
-                .register_rendered_response_id(message.id, response.id)
-                .await;
+    // The response has a source of its own now, edits of the message must not overwrite it
+    data.detach_response(cmd.message.id).await?;
+    let info = WidenInfo { source, ..info };
+    rerender_response(ctx, data, cmd, &submission.token, info, submission.id.get()).await
+}
 
-            return Ok(());
-        }
+/// Renders a failed response again with the other engine, in case the wrong one was picked.
+async fn handle_switch_engine_button_click<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
+        return Ok(());
     };
 
-    let handle = ctx
-        .send(CreateReply::default().attachment(CreateAttachment::bytes(image.png, "typst.png")))
-        .await?;
+    // The button is only shown for a single part, which may be a tagged code block
+    let Ok([part]) = <[_; 1]>::try_from(preprocess::parts(&info.source, info.engine)) else {
+        return answer_unknown_button(ctx, cmd, data).await;
+    };
 
-    let response = handle.message().await?;
+    if !may_act_on(ctx, cmd, data, info.owner, "switch engine").await? {
+        return Ok(());
+    }
 
-    ctx.data()
-        .register_rendered_response_id(message.id, response.id)
-        .await;
+    cmd.defer(ctx).await?;
 
-    Ok(())
+    let engine = part.engine.other();
+    info!(
+        "Switching response {} to {}",
+        cmd.message.id,
+        engine.display_name()
+    );
+    data.set_rendered_engine(cmd.message.id, engine).await?;
+
+    let info = WidenInfo {
+        engine,
+        source: part.source,
+        ..info
+    };
+    rerender_response(ctx, data, cmd, &cmd.token, info, cmd.id.get()).await
 }
 
-async fn handle_event<'a>(
+/// Shows the source of a response to whoever clicked, as a code block or as a file if it does not
+/// fit into a message.
+async fn handle_source_button_click<'a>(
     ctx: &'a serenity::Context,
-    event: &'a FullEvent,
-    _framework: poise::FrameworkContext<'a, BotContext, Error>,
+    cmd: &'a ComponentInteraction,
     data: &'a BotContext,
 ) -> Result<(), Error> {
-    if let FullEvent::InteractionCreate { interaction } = event {
-        if let Some(cmd) = interaction.as_message_component() {
-            trace!("Got interaction from '{}' ({})", cmd.user.name, cmd.user.id);
-            if let Some(member) = &cmd.member {
-                if cmd.data.custom_id.starts_with(DELETE_CUSTOM_ID) {
-                    handle_delete_button_click(ctx, cmd, member).await?;
-                } else if cmd.data.custom_id.starts_with(WIDEN_CUSTOM_ID) {
-                    handle_widen_button_click(ctx, cmd, data).await?;
-                }
-            }
-        }
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
+        return Ok(());
     };
+
+    let extension = info.engine.source_extension();
+    cmd.create_response(ctx, ephemeral_code_block(info.source, extension))
+        .await?;
     Ok(())
 }
 
-async fn handle_widen_button_click<'a>(
+/// Shows a plain text version of the formulas in a response to whoever clicked, for copying
+/// and for people who cannot see the image.
+async fn handle_text_button_click<'a>(
     ctx: &'a serenity::Context,
     cmd: &'a ComponentInteraction,
     data: &'a BotContext,
 ) -> Result<(), Error> {
-    let Some(info) = data.widen_info(cmd.message.id).await else {
-        answer_unknown_button(ctx, cmd).await?;
+    let Some(info) = data.widen_info(cmd.message.id).await? else {
+        answer_unknown_button(ctx, cmd, data).await?;
         return Ok(());
     };
 
-    if info.owner != cmd.user.id {
-        return answer_action_not_allowed(ctx, cmd).await;
-    }
+    let text = preprocess::parts(&info.source, info.engine)
+        .into_iter()
+        .map(|part| alt_text::convert(&part.source, part.engine).unicode)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    cmd.create_response(ctx, ephemeral_code_block(text, "txt"))
+        .await?;
+    Ok(())
+}
 
-    info!("Expanding for '{}' ({})", cmd.user.name, cmd.user.id);
+/// Longest source shown in an embed, longer ones are cut off.
+const MAX_SHOWN_SOURCE_LENGTH: usize = 3500;
 
-    cmd.defer(ctx).await?;
+/// A code block showing source in an embed description, cut off if it is too long.
+fn embed_code_block(source: &str, extension: &str) -> String {
+    let mut source = source.to_string();
+    if source.chars().count() > MAX_SHOWN_SOURCE_LENGTH {
+        source = source.chars().take(MAX_SHOWN_SOURCE_LENGTH).collect();
+        source.push('…');
+    }
+    // A fence in the source would end the block early
+    let source = source.replace("```", "`\u{200B}``");
+    format!("```{extension}\n{source}\n```")
+}
 
-    // Should work as we re-use the LaTeX
-    let image = latex::render_latex(
-        cmd.id.get(),
-        data.renderer_image.clone(),
-        info.latex,
-        ImageWidth::Wide,
-    )
-    .await
-    .unwrap();
+/// An ephemeral code block with the text, or a file if it does not fit into a message.
+fn ephemeral_code_block(text: String, extension: &str) -> CreateInteractionResponse {
+    let code_block = format!("```{extension}\n{text}\n```");
+    let message = CreateInteractionResponseMessage::default().ephemeral(true);
+    let message = if code_block.chars().count() <= MAX_MESSAGE_LENGTH && !text.contains("```") {
+        message.content(code_block)
+    } else {
+        let file_name = format!("source.{extension}");
+        message.add_file(CreateAttachment::bytes(text.into_bytes(), file_name))
+    };
+    CreateInteractionResponse::Message(message)
+}
 
-    cmd.get_response(ctx)
-        .await?
-        .edit(
-            ctx,
-            EditMessage::default()
-                .components(vec![CreateActionRow::Buttons(vec![button_delete(
-                    cmd.user.id,
-                )])])
-                .attachments(
-                    // Since we don't use EditAttachments::keep_all, all previous attachments are deleted.
-                    EditAttachments::default().add(CreateAttachment::bytes(image.png, "latex.png")),
-                ),
-        )
-        .await?;
+async fn handle_cancel_button_click<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+) -> Result<(), Error> {
+    let job_id = cmd
+        .data
+        .custom_id
+        .strip_prefix(CANCEL_CUSTOM_ID)
+        .and_then(|id| id.parse::<JobId>().ok());
+    let Some(job_id) = job_id else {
+        return answer_unknown_button(ctx, cmd, data).await;
+    };
 
-    Ok(())
+    match data.queue.owner(job_id) {
+        Some(owner) if owner != cmd.user.id => answer_action_not_allowed(ctx, cmd, data).await,
+        owner => {
+            // Without an owner the render just finished, and its result replaces the button
+            if owner.is_some() {
+                info!(
+                    "Cancelling render for '{}' ({})",
+                    cmd.user.name, cmd.user.id
+                );
+                data.queue.cancel(job_id);
+            }
+            cmd.create_response(ctx, CreateInteractionResponse::Acknowledge)
+                .await?;
+            Ok(())
+        }
+    }
 }
 
 async fn answer_unknown_button<'a>(
     ctx: &'a serenity::Context,
     cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
 ) -> Result<(), Error> {
+    let locale = interaction_locale(data, cmd).await?;
     cmd.create_response(
         ctx,
         // Maybe use Modal instead?
@@ -353,7 +2017,7 @@ async fn answer_unknown_button<'a>(
                 .ephemeral(true)
                 .embed(
                     CreateEmbed::default()
-                        .title("I don't remember that button. Was it before a restart?"),
+                        .title(locale.tr("I don't remember that button. Was it before a restart?")),
                 ),
         ),
     )
@@ -365,27 +2029,75 @@ async fn answer_unknown_button<'a>(
 async fn handle_delete_button_click<'a>(
     ctx: &'a serenity::Context,
     cmd: &'a ComponentInteraction,
-    member: &'a Member,
+    data: &'a BotContext,
 ) -> Result<(), Error> {
-    let author_id: u64 = cmd
+    let author_id = cmd
         .data
         .custom_id
         .strip_prefix(DELETE_CUSTOM_ID)
-        .unwrap()
-        .parse()
-        .unwrap();
-    if author_id == member.user.id.get() {
-        cmd.message.delete(ctx).await?;
-    } else {
-        answer_action_not_allowed(ctx, cmd).await?;
+        .and_then(|id| id.parse::<u64>().ok())
+        .filter(|&id| id != 0);
+    let Some(author_id) = author_id else {
+        return answer_unknown_button(ctx, cmd, data).await;
+    };
+
+    if may_act_on(ctx, cmd, data, UserId::new(author_id), "delete").await? {
+        // Deleting through the interaction also works where the bot is not a member
+        cmd.create_response(ctx, CreateInteractionResponse::Acknowledge)
+            .await?;
+        cmd.delete_response(ctx).await?;
+        data.forget_response(cmd.message.id).await?;
     }
     Ok(())
 }
 
+/// Whether the member who clicked may moderate renders here: they can manage messages in the
+/// channel or have the configured moderator role.
+async fn is_moderator(data: &BotContext, cmd: &ComponentInteraction) -> Result<bool, Error> {
+    let Some(member) = &cmd.member else {
+        return Ok(false);
+    };
+    if member.permissions.is_some_and(|it| it.manage_messages()) {
+        return Ok(true);
+    }
+
+    let settings = data.settings(cmd.guild_id, cmd.channel_id).await?;
+    Ok(settings
+        .moderator_role
+        .is_some_and(|role| member.roles.contains(&role)))
+}
+
+/// Checks that the user who clicked a button owns the response or is a moderator, and tells them
+/// otherwise. Moderators acting on responses of others are logged.
+async fn may_act_on<'a>(
+    ctx: &'a serenity::Context,
+    cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
+    owner: UserId,
+    action: &str,
+) -> Result<bool, Error> {
+    if owner == cmd.user.id {
+        return Ok(true);
+    }
+
+    if is_moderator(data, cmd).await? {
+        info!(
+            "Moderator '{}' ({}) used {action} on response {} of {owner} in channel {}",
+            cmd.user.name, cmd.user.id, cmd.message.id, cmd.channel_id
+        );
+        return Ok(true);
+    }
+
+    answer_action_not_allowed(ctx, cmd, data).await?;
+    Ok(false)
+}
+
 async fn answer_action_not_allowed<'a>(
     ctx: &'a serenity::Context,
     cmd: &'a ComponentInteraction,
+    data: &'a BotContext,
 ) -> Result<(), Error> {
+    let locale = interaction_locale(data, cmd).await?;
     cmd.create_response(
         ctx,
         // Maybe use Modal instead?
@@ -394,8 +2106,8 @@ async fn answer_action_not_allowed<'a>(
                 .ephemeral(true)
                 .embed(
                     CreateEmbed::default()
-                        .title("You clicked a button.")
-                        .description(interaction_unauthorized_message(&cmd.user)),
+                        .title(locale.tr("You clicked a button."))
+                        .description(locale.tr(interaction_unauthorized_message(&cmd.user))),
                 ),
         ),
     )
@@ -412,18 +2124,93 @@ fn interaction_unauthorized_message(user: &User) -> &'static str {
     }
 }
 
+/// Commands that can never be disabled, or admins could lock themselves out.
+const ALWAYS_ENABLED_COMMANDS: &[&str] = &["config", "register"];
+
+/// The context menu command, which also covers renders triggered by reactions and mentions.
+const RENDER_MESSAGE_COMMAND: &str = "math_context_menu";
+
+/// Subcommands are enabled and disabled along with their top-level command, which is the one
+/// `/config command` offers.
+fn configured_name<'a>(
+    parents: &[&'a poise::Command<BotContext, Error>],
+    command: &'a poise::Command<BotContext, Error>,
+) -> &'a str {
+    parents.first().map_or(&command.name, |root| &root.name)
+}
+
+/// Stops commands disabled in the channel or guild. Renders without a command check
+/// [`RENDER_MESSAGE_COMMAND`] themselves, while automatic rendering has its own switch in
+/// `/config autorender`.
+async fn check_command_enabled(ctx: Context<'_>) -> Result<bool, Error> {
+    let name = configured_name(ctx.parent_commands(), ctx.command());
+    if ALWAYS_ENABLED_COMMANDS.contains(&name) {
+        return Ok(true);
+    }
+
+    let enabled = ctx
+        .data()
+        .command_enabled(ctx.guild_id(), ctx.channel_id(), name)
+        .await?;
+
+    if !enabled {
+        let locale = locale(ctx).await?;
+        ctx.send(
+            CreateReply::default().ephemeral(true).embed(
+                CreateEmbed::default()
+                    .title(locale.tr("This command is disabled here."))
+                    .description(
+                        locale.tr("An administrator can enable it again with `/config command`."),
+                    ),
+            ),
+        )
+        .await?;
+    }
+
+    Ok(enabled)
+}
+
+fn localized_commands() -> Vec<poise::Command<BotContext, Error>> {
+    let mut commands = vec![
+        wolfram(),
+        register(),
+        math_context_menu(),
+        tex(),
+        typst(),
+        render(),
+        config::config(),
+        history::history(),
+        macros::macros(),
+        modules::module(),
+        snippets::snippet(),
+        theme::theme(),
+    ];
+    i18n::localize_commands(&mut commands);
+    commands
+}
+
 pub async fn start_bot(bot_context: BotContext) -> anyhow::Result<()> {
     let token = std::env::var("DISCORD_TOKEN").expect("missing DISCORD_TOKEN");
-    let intents = GatewayIntents::non_privileged();
+
+    let store = bot_context.store.clone();
+    let cache_ttl = bot_context.cache_ttl;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match store.evict_older_than(cache_ttl).await {
+                Ok(removed) => trace!("Evicted {removed} expired cache entries"),
+                Err(e) => error!("Failed to evict expired cache entries: {e:?}"),
+            }
+        }
+    });
+    // Message content is needed to follow edits and to render messages automatically
+    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![
-                wolfram(),
-                register(),
-                tex_context_menu(),
-                typst_context_menu(),
-            ],
+            commands: localized_commands(),
+            command_check: Some(|ctx| Box::pin(check_command_enabled(ctx))),
             prefix_options: PrefixFrameworkOptions {
                 edit_tracker: Some(Arc::new(EditTracker::for_timespan(Duration::from_secs(
                     600,
@@ -436,8 +2223,14 @@ pub async fn start_bot(bot_context: BotContext) -> anyhow::Result<()> {
             },
             reply_callback: Some(|ctx, reply| {
                 if reply.components.is_none() {
+                    // Reply callbacks cannot wait for the store, so only the user's language counts
+                    let locale = ctx
+                        .locale()
+                        .and_then(Locale::from_discord)
+                        .unwrap_or_default();
                     reply.components(vec![CreateActionRow::Buttons(vec![button_delete(
                         ctx.author().id,
+                        locale,
                     )])])
                 } else {
                     reply
@@ -485,3 +2278,32 @@ pub async fn start_bot(bot_context: BotContext) -> anyhow::Result<()> {
 
     Ok(client.start().await?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Store;
+
+    #[tokio::test]
+    async fn disabled_group_commands_block_their_subcommands() {
+        let group = macros::macros();
+        let add = group
+            .subcommands
+            .iter()
+            .find(|it| it.name == "add")
+            .unwrap();
+        let name = configured_name(&[&group], add);
+        assert_eq!(name, "macro");
+        assert_eq!(configured_name(&[], &group), "macro");
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("store.sqlite")).unwrap();
+        let scope = Scope::Guild(GuildId::new(1));
+        store
+            .set_command_enabled(scope, "macro", Some(false))
+            .await
+            .unwrap();
+        let overrides = [store.command_enabled(scope, name).await.unwrap()];
+        assert!(!settings::command_enabled(&overrides));
+    }
+}
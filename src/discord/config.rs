@@ -0,0 +1,371 @@
+//! `/config` commands, letting server admins change settings for their guild or single channels.
+
+use poise::serenity_prelude::{CreateEmbed, GuildChannel, ReactionType, Role};
+use poise::CreateReply;
+
+use super::{answer_ephemeral, locale, Context, Error, ALWAYS_ENABLED_COMMANDS};
+use crate::i18n::Locale;
+use crate::render::Engine;
+use crate::settings::{Scope, Settings};
+use crate::ImageWidth;
+
+/// Configure the bot for this server or a single channel
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "MANAGE_GUILD",
+    required_permissions = "MANAGE_GUILD",
+    subcommands(
+        "show",
+        "engine",
+        "width",
+        "autorender",
+        "moderators",
+        "language",
+        "reaction",
+        "command",
+        "reset"
+    ),
+    subcommand_required
+)]
+pub async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Returns the scope settings are stored for: the channel if given, the guild otherwise.
+fn scope(ctx: Context<'_>, channel: &Option<GuildChannel>) -> Scope {
+    match channel {
+        Some(channel) => Scope::Channel(channel.id),
+        None => Scope::Guild(ctx.guild_id().expect("config is guild only")),
+    }
+}
+
+fn scope_name(channel: &Option<GuildChannel>, locale: Locale) -> String {
+    match channel {
+        Some(channel) => format!("<#{}>", channel.id),
+        None => locale.tr("this server").to_string(),
+    }
+}
+
+fn describe(settings: &Settings, locale: Locale) -> String {
+    let or_inherited =
+        |value: Option<String>| value.unwrap_or_else(|| locale.tr("*inherited*").to_string());
+
+    locale.fmt(
+        "Engine: {engine}\nWidth: {width}\nAuto-render: {auto_render}\n\
+         Moderator role: {moderator_role}\nLanguage: {language}\nRender reaction: {reaction}",
+        &[
+            (
+                "engine",
+                &or_inherited(
+                    settings
+                        .default_engine
+                        .map(|it| it.display_name().to_string()),
+                ),
+            ),
+            (
+                "width",
+                &or_inherited(settings.default_width.map(|it| it.arg_name().to_string())),
+            ),
+            (
+                "auto_render",
+                &or_inherited(
+                    settings
+                        .auto_render
+                        .map(|it| locale.tr(if it { "on" } else { "off" }).to_string()),
+                ),
+            ),
+            (
+                "moderator_role",
+                &or_inherited(settings.moderator_role.map(|it| format!("<@&{it}>"))),
+            ),
+            (
+                "language",
+                &or_inherited(settings.locale.map(|it| it.display_name().to_string())),
+            ),
+            (
+                "reaction",
+                &or_inherited(
+                    settings
+                        .render_reaction
+                        .as_ref()
+                        .map(ReactionType::to_string),
+                ),
+            ),
+        ],
+    )
+}
+
+fn describe_overrides(overrides: &[(String, bool)], locale: Locale) -> String {
+    if overrides.is_empty() {
+        return locale.tr("*none*").to_string();
+    }
+
+    overrides
+        .iter()
+        .map(|(command, enabled)| {
+            let state = locale.tr(if *enabled { "enabled" } else { "disabled" });
+            format!("`{command}`: {state}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Updates the stored settings of a scope and reports the result.
+async fn update_settings(
+    ctx: Context<'_>,
+    channel: Option<GuildChannel>,
+    update: impl FnOnce(&mut Settings),
+) -> Result<(), Error> {
+    let scope = scope(ctx, &channel);
+    let mut settings = ctx.data().store.settings(scope).await?;
+    update(&mut settings);
+    ctx.data().store.set_settings(scope, &settings).await?;
+
+    let locale = locale(ctx).await?;
+    answer_ephemeral(
+        ctx,
+        locale.fmt(
+            "Updated settings for {scope}",
+            &[("scope", &scope_name(&channel, locale))],
+        ),
+        describe(&settings, locale),
+    )
+    .await
+}
+
+/// Show the settings of this server and channel
+#[poise::command(slash_command)]
+async fn show(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("config is guild only");
+    let channel_id = ctx.channel_id();
+    let store = &ctx.data().store;
+
+    let effective = ctx.data().settings(Some(guild_id), channel_id).await?;
+    let locale = locale(ctx).await?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .components(vec![])
+            .embed(
+                CreateEmbed::default()
+                    .title(locale.tr("Settings"))
+                    .field(
+                        locale.tr("This server"),
+                        describe(&store.settings(Scope::Guild(guild_id)).await?, locale),
+                        true,
+                    )
+                    .field(
+                        locale.tr("This channel"),
+                        describe(&store.settings(Scope::Channel(channel_id)).await?, locale),
+                        true,
+                    )
+                    .field(
+                        locale.tr("In effect here"),
+                        describe(
+                            &Settings {
+                                default_engine: Some(effective.default_engine),
+                                default_width: Some(effective.default_width),
+                                auto_render: Some(effective.auto_render),
+                                moderator_role: effective.moderator_role,
+                                locale: effective.locale,
+                                render_reaction: effective.render_reaction,
+                            },
+                            locale,
+                        ),
+                        true,
+                    )
+                    .field(
+                        locale.tr("Commands in this server"),
+                        describe_overrides(
+                            &store.command_overrides(Scope::Guild(guild_id)).await?,
+                            locale,
+                        ),
+                        true,
+                    )
+                    .field(
+                        locale.tr("Commands in this channel"),
+                        describe_overrides(
+                            &store.command_overrides(Scope::Channel(channel_id)).await?,
+                            locale,
+                        ),
+                        true,
+                    ),
+            ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Set the engine used when none is picked explicitly
+#[poise::command(slash_command)]
+async fn engine(
+    ctx: Context<'_>,
+    #[description = "Default engine, leave empty to inherit"] engine: Option<Engine>,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    update_settings(ctx, channel, |settings| settings.default_engine = engine).await
+}
+
+/// Set the width renders start out with
+#[poise::command(slash_command)]
+async fn width(
+    ctx: Context<'_>,
+    #[description = "Default width, leave empty to inherit"] width: Option<ImageWidth>,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    update_settings(ctx, channel, |settings| settings.default_width = width).await
+}
+
+/// Render math in messages automatically
+#[poise::command(slash_command)]
+async fn autorender(
+    ctx: Context<'_>,
+    #[description = "Whether to render automatically, leave empty to inherit"] enabled: Option<
+        bool,
+    >,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    update_settings(ctx, channel, |settings| settings.auto_render = enabled).await
+}
+
+/// Set a role that may delete and resize renders of others, besides members who can manage
+/// messages
+#[poise::command(slash_command)]
+async fn moderators(
+    ctx: Context<'_>,
+    #[description = "Moderator role, leave empty to inherit"] role: Option<Role>,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    update_settings(ctx, channel, |settings| {
+        settings.moderator_role = role.map(|it| it.id)
+    })
+    .await
+}
+
+/// Answer in one language instead of the one each user picked in Discord
+#[poise::command(slash_command)]
+async fn language(
+    ctx: Context<'_>,
+    #[description = "Language of responses, leave empty to inherit"] language: Option<Locale>,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    update_settings(ctx, channel, |settings| settings.locale = language).await
+}
+
+/// Render a message when someone reacts to it with an emoji
+#[poise::command(slash_command)]
+async fn reaction(
+    ctx: Context<'_>,
+    #[description = "Emoji to react with, leave empty to inherit"] emoji: Option<String>,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let emoji = match emoji.map(|it| ReactionType::try_from(it.trim())) {
+        None => None,
+        // Serenity takes any text that is not a custom emoji for a unicode one
+        Some(Ok(ReactionType::Unicode(text)))
+            if !text
+                .chars()
+                .any(|c| c.is_ascii_alphabetic() || c.is_whitespace()) =>
+        {
+            Some(ReactionType::Unicode(text))
+        }
+        Some(Ok(emoji @ ReactionType::Custom { .. })) => Some(emoji),
+        Some(_) => {
+            let locale = locale(ctx).await?;
+            return answer_ephemeral(
+                ctx,
+                locale.tr("Invalid emoji"),
+                locale
+                    .tr("Pick a single emoji, like 🧮 or a custom emoji of this server.")
+                    .to_string(),
+            )
+            .await;
+        }
+    };
+
+    update_settings(ctx, channel, |settings| settings.render_reaction = emoji).await
+}
+
+async fn autocomplete_command<'a>(ctx: Context<'a>, partial: &'a str) -> Vec<String> {
+    configurable_commands(ctx)
+        .filter(|name| name.starts_with(partial))
+        .collect()
+}
+
+fn configurable_commands(ctx: Context<'_>) -> impl Iterator<Item = String> + '_ {
+    ctx.framework()
+        .options()
+        .commands
+        .iter()
+        .map(|command| command.name.clone())
+        .filter(|name| !ALWAYS_ENABLED_COMMANDS.contains(&name.as_str()))
+}
+
+/// Enable or disable a command
+#[poise::command(slash_command)]
+async fn command(
+    ctx: Context<'_>,
+    #[description = "Command to configure"]
+    #[autocomplete = "autocomplete_command"]
+    name: String,
+    #[description = "Whether the command can be used, leave empty to inherit"] enabled: Option<
+        bool,
+    >,
+    #[description = "Only change this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let locale = locale(ctx).await?;
+    if !configurable_commands(ctx).any(|it| it == name) {
+        return answer_ephemeral(
+            ctx,
+            locale.tr("Unknown command"),
+            locale.fmt(
+                "`{name}` is not a command that can be configured.",
+                &[("name", &name)],
+            ),
+        )
+        .await;
+    }
+
+    let scope = scope(ctx, &channel);
+    let store = &ctx.data().store;
+    store.set_command_enabled(scope, &name, enabled).await?;
+
+    answer_ephemeral(
+        ctx,
+        locale.fmt(
+            "Updated commands for {scope}",
+            &[("scope", &scope_name(&channel, locale))],
+        ),
+        describe_overrides(&store.command_overrides(scope).await?, locale),
+    )
+    .await
+}
+
+/// Reset all settings to their inherited values
+#[poise::command(slash_command)]
+async fn reset(
+    ctx: Context<'_>,
+    #[description = "Only reset this channel"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let scope = scope(ctx, &channel);
+    let store = &ctx.data().store;
+
+    store.set_settings(scope, &Settings::default()).await?;
+    for (command, _) in store.command_overrides(scope).await? {
+        store.set_command_enabled(scope, &command, None).await?;
+    }
+
+    let locale = locale(ctx).await?;
+    answer_ephemeral(
+        ctx,
+        locale.fmt(
+            "Reset settings for {scope}",
+            &[("scope", &scope_name(&channel, locale))],
+        ),
+        describe(&Settings::default(), locale),
+    )
+    .await
+}
@@ -0,0 +1,365 @@
+//! `/history` command, letting users page through and search their past renders.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use image::ImageFormat;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteraction, ComponentInteractionCollector, CreateActionRow,
+    CreateAttachment, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditAttachments, EditInteractionResponse, Timestamp,
+};
+use poise::CreateReply;
+
+use super::{
+    answer_ephemeral, embed_code_block, locale, record_history, render_queued, resizable_width,
+    Context, Error, RenderReply,
+};
+use crate::alt_text;
+use crate::i18n::Locale;
+use crate::render::{self, Rendered, RenderedPart};
+use crate::store::{HistoryEntry, HistoryPage, WidenInfo};
+
+/// How long the buttons of a history page keep working after their last use.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+const PREVIOUS: &str = "previous";
+const NEXT: &str = "next";
+const POST: &str = "post";
+const RERENDER: &str = "rerender";
+
+const THUMBNAIL_NAME: &str = "render.png";
+
+/// Longest edge of the thumbnails kept in the history, in pixels. Posting a render again fetches
+/// the full image from the response.
+const THUMBNAIL_SIZE: u32 = 160;
+
+/// Browse your past renders
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Only show renders whose source contains these words"] search: Option<String>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let store = &ctx.data().store;
+    let locale = locale(ctx).await?;
+
+    let mut index = 0;
+    let (Some(mut page), mut total) = store.history_entry(user_id, search.as_deref(), 0).await?
+    else {
+        let description = match &search {
+            Some(search) => locale.fmt(
+                "None of your renders contain `{search}`.",
+                &[("search", search)],
+            ),
+            None => locale
+                .tr("Your renders show up here once you rendered something.")
+                .to_string(),
+        };
+        return answer_ephemeral(ctx, locale.tr("No renders found"), description).await;
+    };
+
+    let prefix = format!("history{}-", ctx.id());
+    let (embed, thumbnail, components) = page_view(&page, index, total, &prefix, locale);
+    let mut reply = CreateReply::default()
+        .ephemeral(true)
+        .embed(embed)
+        .components(components);
+    if let Some(thumbnail) = thumbnail {
+        reply = reply.attachment(thumbnail);
+    }
+    let handle = ctx.send(reply).await?;
+
+    loop {
+        let filter_prefix = prefix.clone();
+        let press = ComponentInteractionCollector::new(ctx)
+            .filter(move |press| {
+                press.user.id == user_id && press.data.custom_id.starts_with(&filter_prefix)
+            })
+            .timeout(PAGE_TIMEOUT)
+            .await;
+        let Some(press) = press else {
+            break;
+        };
+
+        match press.data.custom_id.strip_prefix(&prefix) {
+            Some(PREVIOUS) => index = index.saturating_sub(1),
+            Some(NEXT) => index += 1,
+            Some(POST) => {
+                post(ctx, &press, &page.entry).await?;
+                continue;
+            }
+            Some(RERENDER) => {
+                rerender(ctx, &press, &page.entry).await?;
+                // The queue status took the place of the page while the render waited
+                show_page(ctx, &press, &page, index, total, &prefix, locale).await?;
+                continue;
+            }
+            _ => continue,
+        }
+
+        press
+            .create_response(ctx, CreateInteractionResponse::Acknowledge)
+            .await?;
+
+        // Renders may have been added or dropped in the meantime
+        (page, total) = loop {
+            match store
+                .history_entry(user_id, search.as_deref(), index)
+                .await?
+            {
+                (Some(page), total) => break (page, total),
+                (None, 0) => return Ok(()),
+                (None, total) => index = total - 1,
+            }
+        };
+
+        show_page(ctx, &press, &page, index, total, &prefix, locale).await?;
+    }
+
+    handle
+        .edit(ctx, CreateReply::default().components(vec![]))
+        .await?;
+    Ok(())
+}
+
+/// Replaces the page a button was pressed on.
+async fn show_page(
+    ctx: Context<'_>,
+    press: &ComponentInteraction,
+    page: &HistoryPage,
+    index: usize,
+    total: usize,
+    prefix: &str,
+    locale: Locale,
+) -> Result<(), Error> {
+    let (embed, thumbnail, components) = page_view(page, index, total, prefix, locale);
+    let attachments = thumbnail
+        .into_iter()
+        .fold(EditAttachments::new(), EditAttachments::add);
+    press
+        .edit_response(
+            ctx,
+            EditInteractionResponse::default()
+                .embed(embed)
+                .attachments(attachments)
+                .components(components),
+        )
+        .await?;
+    Ok(())
+}
+
+/// A small PNG of a rendered image, `None` if it cannot be decoded.
+pub(super) fn thumbnail(image: &[u8]) -> Option<Vec<u8>> {
+    let thumbnail = image::load_from_memory(image)
+        .ok()?
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut png = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+/// The embed, thumbnail and buttons showing one entry of the history.
+fn page_view(
+    page: &HistoryPage,
+    index: usize,
+    total: usize,
+    prefix: &str,
+    locale: Locale,
+) -> (CreateEmbed, Option<CreateAttachment>, Vec<CreateActionRow>) {
+    let entry = &page.entry;
+
+    let link = entry.response_id.link(entry.channel_id, entry.guild_id);
+    let description = format!(
+        "[{}]({link})\n{}",
+        locale.tr("Jump to the render"),
+        embed_code_block(&entry.source, entry.engine.source_extension())
+    );
+
+    let mut embed = CreateEmbed::default()
+        .title(locale.fmt(
+            "{engine} render {index} of {total}",
+            &[
+                ("engine", &entry.engine.display_name()),
+                ("index", &(index + 1)),
+                ("total", &total),
+            ],
+        ))
+        .description(description);
+    if let Ok(timestamp) = Timestamp::from_unix_timestamp(page.created_at as i64) {
+        embed = embed.timestamp(timestamp);
+    }
+
+    let thumbnail = entry.thumbnail.as_ref().map(|png| {
+        embed = embed
+            .clone()
+            .thumbnail(format!("attachment://{THUMBNAIL_NAME}"));
+        CreateAttachment::bytes(png.clone(), THUMBNAIL_NAME)
+    });
+
+    let buttons = vec![
+        CreateButton::new(format!("{prefix}{PREVIOUS}"))
+            .label(locale.tr("Newer"))
+            .style(ButtonStyle::Secondary)
+            .disabled(index == 0),
+        CreateButton::new(format!("{prefix}{NEXT}"))
+            .label(locale.tr("Older"))
+            .style(ButtonStyle::Secondary)
+            .disabled(index + 1 >= total),
+        CreateButton::new(format!("{prefix}{POST}"))
+            .label(locale.tr("Post"))
+            .style(ButtonStyle::Primary),
+        CreateButton::new(format!("{prefix}{RERENDER}"))
+            .label(locale.tr("Render again"))
+            .style(ButtonStyle::Primary),
+    ];
+
+    (embed, thumbnail, vec![CreateActionRow::Buttons(buttons)])
+}
+
+/// Posts the image of a past render again, visible to everyone.
+async fn post(
+    ctx: Context<'_>,
+    press: &ComponentInteraction,
+    entry: &HistoryEntry,
+) -> Result<(), Error> {
+    let locale = locale(ctx).await?;
+    let Some(image) = original_image(ctx, entry).await else {
+        press
+            .create_response(
+                ctx,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .ephemeral(true)
+                        .content(
+                            locale.tr("The render is gone, but you can still render it again."),
+                        ),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    press
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let parts = [RenderedPart {
+        engine: entry.engine,
+        result: Ok(Rendered {
+            image,
+            format: entry.request.format,
+            overrun_hbox: false,
+            too_tall: false,
+        }),
+        alt_text: alt_text::convert(&entry.source, entry.engine),
+        // Posting it for everyone must not give away what was hidden
+        spoiler: entry.request.spoiler,
+    }];
+    let response = press
+        .create_followup(
+            ctx,
+            RenderReply::new(&parts, entry.owner, "", None, locale).into_create_followup(),
+        )
+        .await?;
+
+    let info = WidenInfo {
+        owner: entry.owner,
+        engine: entry.engine,
+        source: entry.source.clone(),
+        request: entry.request,
+    };
+    ctx.data().register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+/// Downloads the first image of the response a history entry was recorded for, `None` if it was
+/// deleted or cannot be read.
+async fn original_image(ctx: Context<'_>, entry: &HistoryEntry) -> Option<Vec<u8>> {
+    let response = entry
+        .channel_id
+        .message(ctx, entry.response_id)
+        .await
+        .ok()?;
+    response.attachments.first()?.download().await.ok()
+}
+
+/// Renders the source of a past render again the way it was requested and posts the result.
+async fn rerender(
+    ctx: Context<'_>,
+    press: &ComponentInteraction,
+    entry: &HistoryEntry,
+) -> Result<(), Error> {
+    press
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let data = ctx.data();
+    let settings = data.settings(ctx.guild_id(), ctx.channel_id()).await?;
+    let libraries = data.libraries(ctx.guild_id(), entry.owner).await?;
+    let locale = locale(ctx).await?;
+    let parts = render_queued(
+        ctx.http(),
+        data,
+        entry.owner,
+        Some(&press.token),
+        locale,
+        render::render_input(
+            press.id.get(),
+            &data.renderer_image,
+            entry.engine,
+            &entry.source,
+            &libraries,
+            &entry.request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        return Ok(());
+    };
+    let resized = entry.request.width_cm != settings.default_width.centimetres();
+    let resizable = resizable_width(&parts, entry.request.width_cm, resized);
+
+    let response = press
+        .create_followup(
+            ctx,
+            RenderReply::new(
+                &parts,
+                entry.owner,
+                "Use the Edit button to fix the source.",
+                resizable,
+                locale,
+            )
+            .into_create_followup(),
+        )
+        .await?;
+
+    let info = WidenInfo {
+        owner: entry.owner,
+        engine: entry.engine,
+        source: entry.source.clone(),
+        request: entry.request,
+    };
+    record_history(data, &parts, &info, ctx.guild_id(), &response).await?;
+    data.register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnails_keep_the_aspect_ratio() {
+        let mut png = Vec::new();
+        image::RgbaImage::new(1000, 500)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let small = image::load_from_memory(&thumbnail(&png).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (160, 80));
+        assert_eq!(thumbnail(b"not an image"), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! `/macro` commands, managing the LaTeX macro libraries of users and guilds.
+
+use super::{answer_ephemeral, library, locale, render_queued, Context, Error, Library};
+use crate::i18n::Locale;
+use crate::latex;
+use crate::macros::{InvalidMacro, Macro};
+use crate::render::{self, Engine, Libraries, RenderRequest};
+use crate::theme::Appearance;
+use crate::ImageWidth;
+
+/// Manage LaTeX macros that are available in all your renders
+#[poise::command(
+    slash_command,
+    rename = "macro",
+    subcommands("add", "list", "remove"),
+    subcommand_required
+)]
+pub async fn macros(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+fn code_block(macros: &[Macro]) -> String {
+    let definitions = macros
+        .iter()
+        .map(Macro::definition)
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("```latex\n{definitions}\n```")
+}
+
+/// Add a macro or replace one with the same name
+#[poise::command(slash_command)]
+async fn add(
+    ctx: Context<'_>,
+    #[description = "Name of the macro, e.g. \\R"] name: String,
+    #[description = "Definition, use #1, #2, ... to refer to arguments"] definition: String,
+    #[description = "Number of arguments, guessed from the definition if empty"]
+    #[min = 0]
+    #[max = 9]
+    arguments: Option<u8>,
+    #[description = "Add to the server library instead of yours"] server: Option<bool>,
+) -> Result<(), Error> {
+    let Some(library) = library(ctx, server, "macros").await? else {
+        return Ok(());
+    };
+
+    let locale = locale(ctx).await?;
+    let arguments = arguments.unwrap_or_else(|| Macro::infer_arguments(&definition));
+    let new = match Macro::new(&name, arguments, definition) {
+        Ok(new) => new,
+        Err(e) => {
+            let name = name.trim().trim_start_matches('\\');
+            let description = locale.fmt(e.message(), &[("name", &name)]);
+            return answer_ephemeral(ctx, locale.tr("Invalid macro"), description).await;
+        }
+    };
+
+    ctx.defer_ephemeral().await?;
+    if is_taken(ctx, &new, locale).await? {
+        let description = locale.fmt(InvalidMacro::Taken.message(), &[("name", &new.name)]);
+        return answer_ephemeral(ctx, locale.tr("Invalid macro"), description).await;
+    }
+
+    let store = &ctx.data().store;
+    let replaced = store.add_macro(library.scope_id(), &new).await?;
+
+    let mut description = code_block(std::slice::from_ref(&new));
+    if let Some(replaced) = replaced {
+        description += "\n";
+        description += locale.tr("This replaces the previous definition");
+        description += &format!("\n{}", code_block(&[replaced]));
+    }
+    if let (Library::User(_), Some(guild_id)) = (library, ctx.guild_id()) {
+        let guild_macros = store.macros(guild_id.get()).await?;
+        if let Some(shadowed) = guild_macros.iter().find(|it| it.name == new.name) {
+            description += "\n";
+            description += locale.tr("In your renders it takes precedence over the server macro");
+            description += &format!("\n{}", code_block(std::slice::from_ref(shadowed)));
+        }
+    }
+
+    answer_ephemeral(
+        ctx,
+        locale.fmt(
+            "Added \\{name} to {library}",
+            &[("name", &new.name), ("library", &locale.tr(library.name()))],
+        ),
+        description,
+    )
+    .await
+}
+
+/// Renders a document defining the macro, as only LaTeX knows which commands it and its packages
+/// define. Other failures are left for the renders using the macro to report.
+async fn is_taken(ctx: Context<'_>, new: &Macro, locale: Locale) -> Result<bool, Error> {
+    let data = ctx.data();
+    let libraries = Libraries {
+        latex_preamble: new.definition(),
+        ..Libraries::default()
+    };
+    let request = RenderRequest::new(Appearance::default(), ImageWidth::Normal.centimetres());
+    let rendered = render_queued(
+        ctx.http(),
+        data,
+        ctx.author().id,
+        None,
+        locale,
+        render::render(
+            ctx.id().to_string(),
+            data.renderer_image.clone(),
+            Engine::Latex,
+            "x".to_string(),
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+
+    Ok(match rendered {
+        Some(Err(e)) => latex::already_defined(&format!("{e:#}")) == Some(new.name.as_str()),
+        _ => false,
+    })
+}
+
+/// List the macros available to you
+#[poise::command(slash_command)]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let store = &ctx.data().store;
+    let locale = locale(ctx).await?;
+
+    let mut sections = Vec::new();
+    let user_macros = store.macros(ctx.author().id.get()).await?;
+    if !user_macros.is_empty() {
+        sections.push(format!(
+            "**{}**\n{}",
+            locale.tr("Your macros"),
+            code_block(&user_macros)
+        ));
+    }
+    if let Some(guild_id) = ctx.guild_id() {
+        let guild_macros = store.macros(guild_id.get()).await?;
+        if !guild_macros.is_empty() {
+            sections.push(format!(
+                "**{}**\n{}",
+                locale.tr("Server macros"),
+                code_block(&guild_macros)
+            ));
+        }
+    }
+
+    if sections.is_empty() {
+        sections.push(
+            locale
+                .tr("There are no macros yet, add some with `/macro add`.")
+                .to_string(),
+        );
+    }
+
+    let mut description = sections.join("\n");
+    if description.len() > 4096 {
+        description = locale
+            .tr("Your macros do not fit into a message, sorry.")
+            .to_string();
+    }
+
+    answer_ephemeral(ctx, locale.tr("Macros"), description).await
+}
+
+/// Remove a macro
+#[poise::command(slash_command)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the macro, e.g. \\R"] name: String,
+    #[description = "Remove from the server library instead of yours"] server: Option<bool>,
+) -> Result<(), Error> {
+    let Some(library) = library(ctx, server, "macros").await? else {
+        return Ok(());
+    };
+
+    let name = name.trim().trim_start_matches('\\');
+    let removed = ctx
+        .data()
+        .store
+        .remove_macro(library.scope_id(), name)
+        .await?;
+
+    let locale = locale(ctx).await?;
+    let library_name = locale.tr(library.name());
+    if removed {
+        answer_ephemeral(
+            ctx,
+            locale.fmt(
+                "Removed \\{name} from {library}",
+                &[("name", &name), ("library", &library_name)],
+            ),
+            String::new(),
+        )
+        .await
+    } else {
+        answer_ephemeral(
+            ctx,
+            locale.tr("Unknown macro"),
+            locale.fmt(
+                "There is no macro `\\{name}` in {library}.",
+                &[("name", &name), ("library", &library_name)],
+            ),
+        )
+        .await
+    }
+}
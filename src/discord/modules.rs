@@ -0,0 +1,169 @@
+//! `/module` commands, managing the typst modules of a guild.
+
+use poise::serenity_prelude::Attachment;
+
+use super::{answer_ephemeral, locale, Context, Error};
+
+/// Largest module we accept, in bytes.
+const MAX_MODULE_SIZE: u32 = 100 * 1024;
+
+/// Manage typst modules everybody on this server can import with `#import "guild:name.typ"`
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("add", "list", "remove"),
+    subcommand_required
+)]
+pub async fn module(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Normalizes a module name to `name.typ`, returning `None` for invalid names.
+fn module_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    let name = name.strip_prefix("guild:").unwrap_or(name);
+    let stem = name.strip_suffix(".typ").unwrap_or(name);
+
+    let valid = !stem.is_empty()
+        && stem
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| format!("{stem}.typ"))
+}
+
+async fn answer_invalid_name(ctx: Context<'_>, name: &str) -> Result<(), Error> {
+    let locale = locale(ctx).await?;
+    answer_ephemeral(
+        ctx,
+        locale.tr("Invalid module name"),
+        locale.fmt(
+            "`{name}` is not a valid name, use letters, digits, `-` and `_` only.",
+            &[("name", &name)],
+        ),
+    )
+    .await
+}
+
+/// Upload a module or replace one with the same name
+#[poise::command(
+    slash_command,
+    default_member_permissions = "MANAGE_GUILD",
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn add(
+    ctx: Context<'_>,
+    #[description = "Name to import the module with, e.g. notation"] name: String,
+    #[description = "The .typ file"] file: Attachment,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("module is guild only");
+    let Some(name) = module_name(&name) else {
+        return answer_invalid_name(ctx, &name).await;
+    };
+
+    let locale = locale(ctx).await?;
+    if file.size > MAX_MODULE_SIZE {
+        return answer_ephemeral(
+            ctx,
+            locale.tr("Module too large"),
+            locale.fmt(
+                "Modules may be at most {size} KiB.",
+                &[("size", &(MAX_MODULE_SIZE / 1024))],
+            ),
+        )
+        .await;
+    }
+
+    let Ok(source) = String::from_utf8(file.download().await?) else {
+        return answer_ephemeral(
+            ctx,
+            locale.tr("Invalid module"),
+            locale.tr("The file is not valid UTF-8 text.").to_string(),
+        )
+        .await;
+    };
+
+    let replaced = ctx
+        .data()
+        .store
+        .add_typst_module(guild_id, &name, &source)
+        .await?;
+
+    let title = if replaced {
+        "Replaced module {name}"
+    } else {
+        "Added module {name}"
+    };
+    answer_ephemeral(
+        ctx,
+        locale.fmt(title, &[("name", &name)]),
+        format!(
+            "{}\n```typst\n#import \"guild:{name}\": *\n```",
+            locale.tr("Import it with")
+        ),
+    )
+    .await
+}
+
+/// List the modules of this server
+#[poise::command(slash_command)]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("module is guild only");
+    let names = ctx.data().store.typst_module_names(guild_id).await?;
+    let locale = locale(ctx).await?;
+
+    let description = if names.is_empty() {
+        locale
+            .tr("There are no modules yet, upload some with `/module add`.")
+            .to_string()
+    } else {
+        names
+            .iter()
+            .map(|name| format!("`guild:{name}`"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    answer_ephemeral(ctx, locale.tr("Typst modules"), description).await
+}
+
+/// Remove a module
+#[poise::command(
+    slash_command,
+    default_member_permissions = "MANAGE_GUILD",
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the module"] name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("module is guild only");
+    let Some(name) = module_name(&name) else {
+        return answer_invalid_name(ctx, &name).await;
+    };
+
+    let removed = ctx
+        .data()
+        .store
+        .remove_typst_module(guild_id, &name)
+        .await?;
+
+    let locale = locale(ctx).await?;
+    if removed {
+        answer_ephemeral(
+            ctx,
+            locale.fmt("Removed module {name}", &[("name", &name)]),
+            String::new(),
+        )
+        .await
+    } else {
+        answer_ephemeral(
+            ctx,
+            locale.tr("Unknown module"),
+            locale.fmt(
+                "There is no module `{name}` on this server.",
+                &[("name", &name)],
+            ),
+        )
+        .await
+    }
+}
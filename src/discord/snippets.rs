@@ -0,0 +1,398 @@
+//! `/snippet` commands, saving source under a name to render it again later.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use log::warn;
+use poise::serenity_prelude::{AutocompleteChoice, ModalInteractionCollector};
+
+use super::{
+    answer_ephemeral, cancelled_response, embed_code_block, ephemeral_message, library, locale,
+    modal_text, record_history, render_queued, resizable_width, source_modal, ApplicationContext,
+    BotContext, Context, Error, Library, RenderReply,
+};
+use crate::i18n::Locale;
+use crate::preprocess;
+use crate::render::{self, Engine, RenderRequest};
+use crate::store::{Snippet, WidenInfo};
+
+/// Longest snippet name we accept, in characters.
+const MAX_NAME_LENGTH: usize = 50;
+
+/// Discord shows at most 25 autocomplete choices.
+const MAX_CHOICES: usize = 25;
+
+/// Save and render named snippets of LaTeX or typst code
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("save", "use_snippet", "remove"),
+    subcommand_required
+)]
+pub async fn snippet(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Normalizes a snippet name, returning `None` for invalid names.
+fn snippet_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && !name.chars().any(char::is_control);
+    valid.then(|| name.to_string())
+}
+
+async fn answer_invalid_name(ctx: Context<'_>, name: &str) -> Result<(), Error> {
+    let locale = locale(ctx).await?;
+    answer_ephemeral(
+        ctx,
+        locale.tr("Invalid snippet name"),
+        locale.fmt(
+            "`{name}` is not a valid name, use at most {length} characters.",
+            &[("name", &name), ("length", &MAX_NAME_LENGTH)],
+        ),
+    )
+    .await
+}
+
+/// Snippets available to the author by name, their own ones shadowing those of the server.
+/// Returns whether each belongs to the server.
+async fn available_snippets(ctx: Context<'_>) -> anyhow::Result<Vec<(String, bool)>> {
+    let store = &ctx.data().store;
+
+    let mut names: Vec<_> = store
+        .snippet_names(ctx.author().id.get())
+        .await?
+        .into_iter()
+        .map(|name| (name, false))
+        .collect();
+    if let Some(guild_id) = ctx.guild_id() {
+        for name in store.snippet_names(guild_id.get()).await? {
+            if !names.iter().any(|(it, _)| *it == name) {
+                names.push((name, true));
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+async fn autocomplete_snippet(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let names = match available_snippets(ctx).await {
+        Ok(names) => names,
+        Err(e) => {
+            warn!("Could not list snippets: {e}");
+            return Vec::new();
+        }
+    };
+
+    let locale = locale(ctx).await.unwrap_or_default();
+    let partial = partial.to_lowercase();
+    names
+        .into_iter()
+        .filter(|(name, _)| name.to_lowercase().contains(&partial))
+        .take(MAX_CHOICES)
+        .map(|(name, server)| {
+            let label = if server {
+                locale.fmt("{name} (server)", &[("name", &name)])
+            } else {
+                name.clone()
+            };
+            AutocompleteChoice::new(label, name)
+        })
+        .collect()
+}
+
+/// Looks up a snippet of the author, falling back to the server's.
+async fn find_snippet(ctx: Context<'_>, name: &str) -> anyhow::Result<Option<Snippet>> {
+    let data = ctx.data();
+    if let Some(snippet) = data.store.snippet(ctx.author().id.get(), name).await? {
+        return Ok(Some(snippet));
+    }
+    match ctx.guild_id() {
+        Some(guild_id) => data.store.snippet(guild_id.get(), name).await,
+        None => Ok(None),
+    }
+}
+
+/// Stores a snippet, returning the title and description of the confirmation.
+async fn store_snippet(
+    data: &BotContext,
+    library: Library,
+    snippet: &Snippet,
+    locale: Locale,
+) -> anyhow::Result<(String, String)> {
+    let replaced = data.store.add_snippet(library.scope_id(), snippet).await?;
+
+    let title = if replaced {
+        "Replaced snippet {name} in {library}"
+    } else {
+        "Saved snippet {name} in {library}"
+    };
+    Ok((
+        locale.fmt(
+            title,
+            &[
+                ("name", &snippet.name),
+                ("library", &locale.tr(library.name())),
+            ],
+        ),
+        format!(
+            "{}\n{}",
+            locale.fmt(
+                "Render it with `/snippet use name:{name}`",
+                &[("name", &snippet.name)]
+            ),
+            embed_code_block(&snippet.source, snippet.engine.source_extension())
+        ),
+    ))
+}
+
+/// Save code entered in a text box under a name, or the message you reply to with the prefix
+/// command
+///
+/// Prefix commands cannot answer ephemerally, so saving a replied-to message is confirmed publicly.
+#[poise::command(slash_command, prefix_command)]
+async fn save(
+    ctx: Context<'_>,
+    #[description = "Name to render the snippet with"] name: String,
+    #[description = "Engine rendering the snippet, defaults to the one of this channel"]
+    engine: Option<Engine>,
+    #[description = "Save to the server library instead of yours"] server: Option<bool>,
+) -> Result<(), Error> {
+    let Some(library) = library(ctx, server, "snippets").await? else {
+        return Ok(());
+    };
+    let Some(name) = snippet_name(&name) else {
+        return answer_invalid_name(ctx, &name).await;
+    };
+
+    let settings = ctx
+        .data()
+        .settings(ctx.guild_id(), ctx.channel_id())
+        .await?;
+    let fallback = engine.unwrap_or(settings.default_engine);
+
+    match ctx {
+        poise::Context::Application(ctx) => {
+            save_from_modal(ctx, library, name, engine, fallback).await
+        }
+        // Only prefix commands can be used as a reply. Their answers are visible to everyone.
+        poise::Context::Prefix(prefix) => {
+            let locale = locale(ctx).await?;
+            let Some(message) = prefix.msg.referenced_message.as_deref() else {
+                return answer_ephemeral(
+                    ctx,
+                    locale.tr("Nothing to save"),
+                    locale
+                        .tr("Reply to the message with the code you want to save.")
+                        .to_string(),
+                )
+                .await;
+            };
+
+            let mut parts = preprocess::parts(&message.content, fallback);
+            if parts.len() != 1 {
+                return answer_ephemeral(
+                    ctx,
+                    locale.tr("Several code blocks"),
+                    locale
+                        .tr("A snippet can only hold a single code block.")
+                        .to_string(),
+                )
+                .await;
+            }
+            let part = parts.remove(0);
+            let snippet = Snippet {
+                name,
+                engine: part.engine,
+                source: part.source,
+            };
+
+            let (title, description) = store_snippet(ctx.data(), library, &snippet, locale).await?;
+            answer_ephemeral(ctx, title, description).await
+        }
+    }
+}
+
+/// Asks for the source of a snippet in a modal, prefilled with the snippet it replaces.
+async fn save_from_modal(
+    ctx: ApplicationContext<'_>,
+    library: Library,
+    name: String,
+    engine: Option<Engine>,
+    fallback: Engine,
+) -> Result<(), Error> {
+    let previous = ctx.data().store.snippet(library.scope_id(), &name).await?;
+    let engine = engine
+        .or(previous.as_ref().map(|it| it.engine))
+        .unwrap_or(fallback);
+    let previous_source = previous.map(|it| it.source).unwrap_or_default();
+    let locale = locale(ctx.into()).await?;
+
+    let custom_id = ctx.interaction.id.to_string();
+    ctx.interaction
+        .create_response(
+            ctx,
+            source_modal(
+                Some(engine),
+                &custom_id,
+                &locale.fmt("Snippet {name}", &[("name", &name)]),
+                &previous_source,
+            ),
+        )
+        .await?;
+    ctx.has_sent_initial_response.store(true, Ordering::SeqCst);
+
+    let submission = ModalInteractionCollector::new(ctx)
+        .filter(move |modal| modal.data.custom_id == custom_id)
+        .timeout(Duration::from_secs(60 * 60))
+        .await;
+    let Some(submission) = submission else {
+        return Ok(());
+    };
+    let Some(source) = modal_text(&submission.data, "source") else {
+        return Ok(());
+    };
+
+    let snippet = Snippet {
+        name,
+        engine,
+        source,
+    };
+    let (title, description) = store_snippet(ctx.data(), library, &snippet, locale).await?;
+    submission
+        .create_response(ctx, ephemeral_message(&title, description))
+        .await?;
+    Ok(())
+}
+
+/// Render a saved snippet
+#[poise::command(slash_command, rename = "use")]
+async fn use_snippet(
+    ctx: ApplicationContext<'_>,
+    #[description = "Name of the snippet"]
+    #[autocomplete = "autocomplete_snippet"]
+    name: String,
+) -> Result<(), Error> {
+    let Some(snippet) = find_snippet(ctx.into(), name.trim()).await? else {
+        let locale = locale(ctx.into()).await?;
+        return answer_ephemeral(
+            ctx.into(),
+            locale.tr("Unknown snippet"),
+            locale.fmt(
+                "There is no snippet `{name}`, save one with `/snippet save`.",
+                &[("name", &name)],
+            ),
+        )
+        .await;
+    };
+
+    ctx.defer().await?;
+
+    let settings = ctx
+        .data()
+        .settings(ctx.guild_id(), ctx.channel_id())
+        .await?;
+    let libraries = ctx
+        .data()
+        .libraries(ctx.guild_id(), ctx.author().id)
+        .await?;
+    let appearance = ctx.data().appearance(ctx.author().id).await?;
+    let request = RenderRequest::new(appearance, settings.default_width.centimetres());
+    let locale = locale(ctx.into()).await?;
+    let parts = render_queued(
+        ctx.http(),
+        ctx.data(),
+        ctx.author().id,
+        Some(&ctx.interaction.token),
+        locale,
+        render::render_input(
+            ctx.id(),
+            &ctx.data().renderer_image,
+            snippet.engine,
+            &snippet.source,
+            &libraries,
+            &request,
+        ),
+    )
+    .await?;
+    let Some(parts) = parts else {
+        ctx.interaction
+            .edit_response(ctx, cancelled_response(locale))
+            .await?;
+        return Ok(());
+    };
+    let resizable = resizable_width(&parts, settings.default_width.centimetres(), false);
+
+    let response = ctx
+        .interaction
+        .edit_response(
+            ctx,
+            RenderReply::new(
+                &parts,
+                ctx.author().id,
+                "You can fix the snippet with /snippet save.",
+                resizable,
+                locale,
+            )
+            .into_edit_interaction_response(),
+        )
+        .await?;
+
+    let info = WidenInfo {
+        owner: ctx.author().id,
+        engine: snippet.engine,
+        source: snippet.source,
+        request,
+    };
+    record_history(ctx.data(), &parts, &info, ctx.guild_id(), &response).await?;
+    ctx.data().register_widen_info(response.id, info).await?;
+    Ok(())
+}
+
+/// Remove a snippet
+#[poise::command(slash_command)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the snippet"]
+    #[autocomplete = "autocomplete_snippet"]
+    name: String,
+    #[description = "Remove from the server library instead of yours"] server: Option<bool>,
+) -> Result<(), Error> {
+    let Some(library) = library(ctx, server, "snippets").await? else {
+        return Ok(());
+    };
+
+    let name = name.trim();
+    let removed = ctx
+        .data()
+        .store
+        .remove_snippet(library.scope_id(), name)
+        .await?;
+
+    let locale = locale(ctx).await?;
+    let library_name = locale.tr(library.name());
+    if removed {
+        answer_ephemeral(
+            ctx,
+            locale.fmt(
+                "Removed snippet {name} from {library}",
+                &[("name", &name), ("library", &library_name)],
+            ),
+            String::new(),
+        )
+        .await
+    } else {
+        answer_ephemeral(
+            ctx,
+            locale.tr("Unknown snippet"),
+            locale.fmt(
+                "There is no snippet `{name}` in {library}.",
+                &[("name", &name), ("library", &library_name)],
+            ),
+        )
+        .await
+    }
+}
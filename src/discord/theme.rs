@@ -0,0 +1,34 @@
+//! `/theme` command, letting users pick the default look of their renders.
+
+use super::{answer_ephemeral, locale, Context, Error};
+use crate::theme::{Appearance, Theme};
+
+/// Pick the colors your renders use by default
+#[poise::command(slash_command)]
+pub async fn theme(
+    ctx: Context<'_>,
+    #[description = "Palette, e.g. Light if you use Discord's light theme"] theme: Option<Theme>,
+    #[description = "Leave out the background, so the image blends into any theme"]
+    transparent: Option<bool>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let current = ctx.data().appearance(user_id).await?;
+    let locale = locale(ctx).await?;
+
+    if theme.is_none() && transparent.is_none() {
+        return answer_ephemeral(ctx, locale.tr("Your theme"), current.describe(locale)).await;
+    }
+
+    let appearance = Appearance {
+        theme: theme.unwrap_or(current.theme),
+        transparent: transparent.unwrap_or(current.transparent),
+    };
+    ctx.data().store.set_appearance(user_id, appearance).await?;
+
+    answer_ephemeral(
+        ctx,
+        locale.tr("Updated your theme"),
+        appearance.describe(locale),
+    )
+    .await
+}
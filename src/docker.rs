@@ -2,12 +2,13 @@
 
 use std::{
     ffi::OsString,
+    io::Write,
     process::{Output, Stdio},
     time::Duration,
 };
 
 use anyhow::bail;
-use log::info;
+use log::{error, info, warn};
 use tokio::{
     io::AsyncWriteExt,
     process::{Child, Command},
@@ -37,16 +38,28 @@ impl DockerCommand {
     pub async fn run(self, input: &str) -> anyhow::Result<Output> {
         pull_docker_image(&self.image).await?;
 
-        let child = spawn_runner(&self.name, &self.image, &self.args, input).await?;
+        let mut child = spawn_runner(&self.name, &self.image, &self.args)?;
+        // Armed before writing the input, so that a render abandoned meanwhile kills its runner
+        let guard = RunnerGuard {
+            name: Some(self.name.clone()),
+        };
+        child
+            .stdin
+            .as_mut()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .await?;
 
         let output = match time::timeout(Duration::from_secs(15), child.wait_with_output()).await {
             Ok(output) => output?,
             Err(_elapsed) => {
                 info!("Runner {:?} timed out, killing it", self.name);
+                guard.disarm();
                 kill_runner(&self.name).await?;
                 bail!("Timeout reached")
             }
         };
+        guard.disarm();
 
         if !output.status.success() {
             bail!(
@@ -61,6 +74,33 @@ impl DockerCommand {
     }
 }
 
+/// Kills a runner whose render was abandoned, e.g. because it was cancelled. Dropping the
+/// `docker run` process alone would leave the container running.
+struct RunnerGuard {
+    name: Option<String>,
+}
+
+impl RunnerGuard {
+    fn disarm(mut self) {
+        self.name = None;
+    }
+}
+
+impl Drop for RunnerGuard {
+    fn drop(&mut self) {
+        let Some(name) = self.name.take() else {
+            return;
+        };
+
+        info!("Render in runner {name:?} was abandoned, killing it");
+        tokio::spawn(async move {
+            if let Err(e) = kill_runner(&name).await {
+                warn!("{e}");
+            }
+        });
+    }
+}
+
 async fn pull_docker_image(image: &str) -> anyhow::Result<()> {
     info!("Pulling image: {image:?}");
 
@@ -82,13 +122,8 @@ async fn pull_docker_image(image: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn spawn_runner(
-    name: &str,
-    image: &str,
-    args: &[OsString],
-    input: &str,
-) -> anyhow::Result<Child> {
-    let mut child = Command::new("docker")
+fn spawn_runner(name: &str, image: &str, args: &[OsString]) -> anyhow::Result<Child> {
+    let child = Command::new("docker")
         .arg("run")
         .arg("--pids-limit=5000")
         .arg("--memory=500M")
@@ -107,13 +142,6 @@ async fn spawn_runner(
         .stdout(Stdio::piped())
         .spawn()?;
 
-    child
-        .stdin
-        .as_mut()
-        .unwrap()
-        .write_all(input.as_bytes())
-        .await?;
-
     Ok(child)
 }
 
@@ -134,3 +162,69 @@ async fn kill_runner(name: &str) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// What a renderer produced, see [`write_renderer_output`] for the wire format.
+pub struct RendererOutput {
+    /// The encoded image, see [`crate::render::OutputFormat`].
+    pub image: Vec<u8>,
+    /// Whether the content did not fit into the maximum width.
+    pub overflow: bool,
+    /// Whether the content is taller than [`crate::render::MAX_HEIGHT_CM`].
+    pub too_tall: bool,
+}
+
+const OVERFLOW_FLAG: u8 = 1;
+const TOO_TALL_FLAG: u8 = 2;
+
+/// Writes the result of a renderer to stdout: a byte flagging an error, followed by either the
+/// error message or a byte with overflow flags and the image.
+pub fn write_renderer_output(result: anyhow::Result<RendererOutput>) {
+    let mut stdout = std::io::stdout();
+    match result {
+        Ok(output) => {
+            stdout.write_all(&[0]).expect("write error failed");
+            let mut flags = 0;
+            if output.overflow {
+                flags |= OVERFLOW_FLAG;
+            }
+            if output.too_tall {
+                flags |= TOO_TALL_FLAG;
+            }
+            stdout.write_all(&[flags]).expect("write error failed");
+            stdout
+                .write_all(&output.image)
+                .expect("could not write image");
+        }
+        Err(err) => {
+            stdout.write_all(&[1]).expect("write error failed");
+            write!(stdout, "{err}").expect("could not write error");
+        }
+    }
+}
+
+/// Parses what a runner wrote with [`write_renderer_output`].
+pub fn read_renderer_output(output: &Output) -> anyhow::Result<RendererOutput> {
+    if output.stdout.first() == Some(&1) {
+        let stdout = String::from_utf8_lossy(&output.stdout[1..]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        info!("Render failed:\nStdout:\n{stdout}\nStderr:\n{stderr}");
+        bail!("{}", stdout);
+    }
+
+    if output.stdout.len() < 3 {
+        error!(
+            "Renderer output too short with {}.\nStdout:{}\nStderr:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        bail!("Renderer output not long enough");
+    }
+
+    let flags = output.stdout[1];
+    Ok(RendererOutput {
+        image: output.stdout[2..].to_vec(),
+        overflow: flags & OVERFLOW_FLAG != 0,
+        too_tall: flags & TOO_TALL_FLAG != 0,
+    })
+}
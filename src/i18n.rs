@@ -0,0 +1,220 @@
+//! Translations of bot responses and application commands.
+//!
+//! Texts are written in English in the code and double as keys into the catalogs of the other
+//! languages, so a text without translation stays English. Placeholders like `{engine}` are
+//! filled in after translating, translations may reorder them.
+
+use std::fmt::Display;
+
+mod de;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Locale {
+    #[default]
+    #[name = "English"]
+    English,
+    #[name = "Deutsch"]
+    German,
+}
+
+/// Translations into one language.
+struct Catalog {
+    /// Translated texts by their English original.
+    messages: &'static [(&'static str, &'static str)],
+    /// Slash command names by qualified name, like `config width`. Discord only allows lowercase
+    /// names without spaces.
+    command_names: &'static [(&'static str, &'static str)],
+    /// Slash command parameter names, with the same restrictions.
+    parameter_names: &'static [(&'static str, &'static str)],
+}
+
+fn lookup(table: &'static [(&'static str, &'static str)], key: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(original, _)| *original == key)
+        .map(|(_, translated)| *translated)
+}
+
+impl Locale {
+    /// All languages we have a catalog for.
+    const TRANSLATED: [Locale; 1] = [Locale::German];
+
+    /// Stable identifier, used when persisting the locale.
+    pub fn key(self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::German => "de",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "en" => Some(Locale::English),
+            "de" => Some(Locale::German),
+            _ => None,
+        }
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Locale::English => "English",
+            Locale::German => "Deutsch",
+        }
+    }
+
+    /// The language of a Discord locale like `de` or `en-US`, `None` if we do not speak it.
+    pub fn from_discord(locale: &str) -> Option<Self> {
+        let language = locale.split('-').next().unwrap_or(locale);
+        Self::from_key(language)
+    }
+
+    /// The Discord locale command localizations are registered for.
+    fn discord_locale(self) -> &'static str {
+        match self {
+            Locale::English => "en-US",
+            Locale::German => "de",
+        }
+    }
+
+    fn catalog(self) -> Option<&'static Catalog> {
+        match self {
+            Locale::English => None,
+            Locale::German => Some(&de::CATALOG),
+        }
+    }
+
+    /// Translates an English text.
+    pub fn tr(self, text: &'static str) -> &'static str {
+        self.catalog()
+            .and_then(|catalog| lookup(catalog.messages, text))
+            .unwrap_or(text)
+    }
+
+    /// Translates an English text and fills in its `{name}` placeholders.
+    pub fn fmt(self, text: &'static str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+        let mut result = self.tr(text).to_string();
+        for (name, value) in args {
+            result = result.replace(&format!("{{{name}}}"), &value.to_string());
+        }
+        result
+    }
+}
+
+/// Adds the translated names and descriptions of commands, their subcommands, parameters and
+/// choices, which Discord shows to users with a matching client language.
+pub fn localize_commands<U, E>(commands: &mut [poise::Command<U, E>]) {
+    localize(commands, "");
+}
+
+/// Qualified names are only filled in when the framework starts, so `parent` tracks them.
+fn localize<U, E>(commands: &mut [poise::Command<U, E>], parent: &str) {
+    for command in commands {
+        let qualified_name = if parent.is_empty() {
+            command.name.clone()
+        } else {
+            format!("{parent} {}", command.name)
+        };
+
+        for locale in Locale::TRANSLATED {
+            let Some(catalog) = locale.catalog() else {
+                continue;
+            };
+            let discord_locale = locale.discord_locale().to_string();
+
+            // Context menu entries are shown by their own name instead
+            let name = match &command.context_menu_name {
+                Some(name) => lookup(catalog.messages, name),
+                None => lookup(catalog.command_names, &qualified_name),
+            };
+            if let Some(name) = name {
+                command
+                    .name_localizations
+                    .insert(discord_locale.clone(), name.to_string());
+            }
+            if let Some(description) = command
+                .description
+                .as_deref()
+                .and_then(|it| lookup(catalog.messages, it))
+            {
+                command
+                    .description_localizations
+                    .insert(discord_locale.clone(), description.to_string());
+            }
+
+            for parameter in &mut command.parameters {
+                if let Some(name) = lookup(catalog.parameter_names, &parameter.name) {
+                    parameter
+                        .name_localizations
+                        .insert(discord_locale.clone(), name.to_string());
+                }
+                if let Some(description) = parameter
+                    .description
+                    .as_deref()
+                    .and_then(|it| lookup(catalog.messages, it))
+                {
+                    parameter
+                        .description_localizations
+                        .insert(discord_locale.clone(), description.to_string());
+                }
+                for choice in &mut parameter.choices {
+                    if let Some(name) = lookup(catalog.messages, &choice.name) {
+                        choice
+                            .localizations
+                            .insert(discord_locale.clone(), name.to_string());
+                    }
+                }
+            }
+        }
+
+        localize(&mut command.subcommands, &qualified_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names of the `{name}` placeholders in a text, sorted.
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names = text
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+            .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn catalogs_are_consistent() {
+        for locale in Locale::TRANSLATED {
+            let messages = locale.catalog().unwrap().messages;
+            for (idx, (original, translated)) in messages.iter().enumerate() {
+                assert!(
+                    !messages[..idx].iter().any(|(it, _)| it == original),
+                    "{original:?} is translated twice"
+                );
+                assert_eq!(
+                    placeholders(original),
+                    placeholders(translated),
+                    "placeholders of {original:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            Locale::German.fmt("Render {engine}", &[("engine", &"LaTeX")]),
+            "LaTeX rendern"
+        );
+        assert_eq!(
+            Locale::English.fmt("Render {engine}", &[("engine", &"LaTeX")]),
+            "Render LaTeX"
+        );
+        // Texts without translation stay English
+        assert_eq!(Locale::German.tr("not translated"), "not translated");
+    }
+}
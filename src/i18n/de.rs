@@ -0,0 +1,572 @@
+//! German translations.
+
+use super::Catalog;
+
+pub(super) const CATALOG: Catalog = Catalog {
+    messages: &[
+        // Buttons
+        ("Delete", "Löschen"),
+        ("Edit", "Bearbeiten"),
+        ("Source", "Quelltext"),
+        ("Copy as text", "Als Text kopieren"),
+        ("Expand", "Verbreitern"),
+        ("Narrower", "Schmaler"),
+        ("Width", "Breite"),
+        ("Cancel", "Abbrechen"),
+        ("Try as {engine}", "Als {engine} versuchen"),
+        // Renders
+        ("Long render", "Langes Bild"),
+        (
+            "This image is taller than {height}cm, it might be easier to read when split into \
+             several parts.",
+            "Dieses Bild ist höher als {height}cm, in mehrere Teile aufgeteilt ist es vielleicht \
+             leichter zu lesen.",
+        ),
+        (
+            "Block {block}: {description}",
+            "Block {block}: {description}",
+        ),
+        (
+            "Error rendering {engine}",
+            "Fehler beim Rendern von {engine}",
+        ),
+        (
+            "Error rendering {engine} (block {block})",
+            "Fehler beim Rendern von {engine} (Block {block})",
+        ),
+        (
+            "You can edit your message and try again.",
+            "Du kannst deine Nachricht bearbeiten und es erneut versuchen.",
+        ),
+        (
+            "You can run the command again.",
+            "Du kannst den Befehl erneut ausführen.",
+        ),
+        (
+            "You can edit the source and try again.",
+            "Du kannst den Quelltext bearbeiten und es erneut versuchen.",
+        ),
+        (
+            "Use the Edit button to fix the source.",
+            "Korrigiere den Quelltext mit dem Bearbeiten-Knopf.",
+        ),
+        (
+            "You can fix the snippet with /snippet save.",
+            "Du kannst das Snippet mit /snippet speichern korrigieren.",
+        ),
+        ("Render {engine}", "{engine} rendern"),
+        ("Edit {engine}", "{engine} bearbeiten"),
+        ("Wolfram Alpha's result", "Ergebnis von Wolfram Alpha"),
+        // Render queue
+        (
+            "Waiting for a free renderer, you are number {position} in the queue.",
+            "Warte auf einen freien Renderer, du bist Nummer {position} in der Warteschlange.",
+        ),
+        ("Rendering...", "Wird gerendert..."),
+        ("Render cancelled", "Rendern abgebrochen"),
+        // Width
+        ("Change width", "Breite ändern"),
+        ("Width in centimetres", "Breite in Zentimetern"),
+        ("{min} to {max}", "{min} bis {max}"),
+        ("Invalid width", "Ungültige Breite"),
+        (
+            "Please enter a width between {min} and {max} centimetres.",
+            "Bitte gib eine Breite zwischen {min} und {max} Zentimetern ein.",
+        ),
+        ("Source too long", "Quelltext zu lang"),
+        (
+            "Discord only lets me prefill {length} characters, edit your original message \
+             instead.",
+            "Discord lässt mich nur {length} Zeichen vorausfüllen, bearbeite stattdessen deine \
+             ursprüngliche Nachricht.",
+        ),
+        // Buttons of others and unknown buttons
+        (
+            "I don't remember that button. Was it before a restart?",
+            "An diesen Knopf erinnere ich mich nicht. War er vor einem Neustart?",
+        ),
+        ("You clicked a button.", "Du hast einen Knopf gedrückt."),
+        (
+            "Bad bean, this isn't yours to click!",
+            "Böse Bohne, das darfst du nicht drücken!",
+        ),
+        (
+            "Good job. But this output was not generated for you, you cannot modify it.",
+            "Gut gemacht. Aber diese Ausgabe wurde nicht für dich erzeugt, du kannst sie nicht \
+             ändern.",
+        ),
+        // Permissions
+        (
+            "This command is disabled here.",
+            "Dieser Befehl ist hier deaktiviert.",
+        ),
+        (
+            "An administrator can enable it again with `/config command`.",
+            "Ein Administrator kann ihn mit `/einstellungen befehl` wieder aktivieren.",
+        ),
+        ("Not in a server", "Nicht auf einem Server"),
+        (
+            "Server {what} can only be managed from within a server.",
+            "Server-{what} können nur auf einem Server verwaltet werden.",
+        ),
+        ("Missing permissions", "Fehlende Berechtigungen"),
+        (
+            "You need the *Manage Server* permission to change server {what}.",
+            "Du brauchst die Berechtigung *Server verwalten*, um Server-{what} zu ändern.",
+        ),
+        ("macros", "Makros"),
+        ("snippets", "Snippets"),
+        ("your library", "deiner Bibliothek"),
+        ("the server library", "der Server-Bibliothek"),
+        // Settings
+        ("Settings", "Einstellungen"),
+        ("This server", "Dieser Server"),
+        ("This channel", "Dieser Kanal"),
+        ("In effect here", "Hier gültig"),
+        ("Commands in this server", "Befehle auf diesem Server"),
+        ("Commands in this channel", "Befehle in diesem Kanal"),
+        ("this server", "diesen Server"),
+        ("*inherited*", "*geerbt*"),
+        ("*none*", "*keine*"),
+        ("on", "an"),
+        ("off", "aus"),
+        ("enabled", "aktiviert"),
+        ("disabled", "deaktiviert"),
+        (
+            "Engine: {engine}\nWidth: {width}\nAuto-render: {auto_render}\n\
+             Moderator role: {moderator_role}\nLanguage: {language}\nRender reaction: {reaction}",
+            "Engine: {engine}\nBreite: {width}\nAutomatisch rendern: {auto_render}\n\
+             Moderatorrolle: {moderator_role}\nSprache: {language}\nRender-Reaktion: {reaction}",
+        ),
+        (
+            "Updated settings for {scope}",
+            "Einstellungen für {scope} geändert",
+        ),
+        (
+            "Updated commands for {scope}",
+            "Befehle für {scope} geändert",
+        ),
+        (
+            "Reset settings for {scope}",
+            "Einstellungen für {scope} zurückgesetzt",
+        ),
+        ("Invalid emoji", "Ungültiges Emoji"),
+        (
+            "Pick a single emoji, like 🧮 or a custom emoji of this server.",
+            "Wähle ein einzelnes Emoji, etwa 🧮 oder ein eigenes Emoji dieses Servers.",
+        ),
+        ("Unknown command", "Unbekannter Befehl"),
+        (
+            "`{name}` is not a command that can be configured.",
+            "`{name}` ist kein Befehl, der eingestellt werden kann.",
+        ),
+        // Macros
+        ("Macros", "Makros"),
+        ("Invalid macro", "Ungültiges Makro"),
+        ("Unknown macro", "Unbekanntes Makro"),
+        (
+            "Macro names may only consist of letters, but `{name}` does not.",
+            "Makronamen dürfen nur aus Buchstaben bestehen, `{name}` tut das nicht.",
+        ),
+        (
+            "LaTeX macros can take at most 9 arguments.",
+            "LaTeX-Makros können höchstens 9 Argumente haben.",
+        ),
+        (
+            "The braces in the body of `\\{name}` are not balanced.",
+            "Die Klammern in der Definition von `\\{name}` sind nicht ausgeglichen.",
+        ),
+        (
+            "`\\{name}` is already defined by LaTeX or a package, pick another name.",
+            "`\\{name}` ist schon von LaTeX oder einem Paket definiert, wähle einen anderen \
+             Namen.",
+        ),
+        (
+            "This replaces the previous definition",
+            "Das ersetzt die bisherige Definition",
+        ),
+        (
+            "In your renders it takes precedence over the server macro",
+            "In deinen Bildern hat es Vorrang vor dem Server-Makro",
+        ),
+        (
+            "Added \\{name} to {library}",
+            "\\{name} zu {library} hinzugefügt",
+        ),
+        (
+            "Removed \\{name} from {library}",
+            "\\{name} aus {library} entfernt",
+        ),
+        (
+            "There is no macro `\\{name}` in {library}.",
+            "In {library} gibt es kein Makro `\\{name}`.",
+        ),
+        ("Your macros", "Deine Makros"),
+        ("Server macros", "Server-Makros"),
+        (
+            "There are no macros yet, add some with `/macro add`.",
+            "Es gibt noch keine Makros, füge welche mit `/makro hinzufügen` hinzu.",
+        ),
+        (
+            "Your macros do not fit into a message, sorry.",
+            "Deine Makros passen leider nicht in eine Nachricht.",
+        ),
+        // Typst modules
+        ("Typst modules", "Typst-Module"),
+        ("Invalid module name", "Ungültiger Modulname"),
+        (
+            "`{name}` is not a valid name, use letters, digits, `-` and `_` only.",
+            "`{name}` ist kein gültiger Name, verwende nur Buchstaben, Ziffern, `-` und `_`.",
+        ),
+        ("Module too large", "Modul zu groß"),
+        (
+            "Modules may be at most {size} KiB.",
+            "Module dürfen höchstens {size} KiB groß sein.",
+        ),
+        ("Invalid module", "Ungültiges Modul"),
+        (
+            "The file is not valid UTF-8 text.",
+            "Die Datei ist kein gültiger UTF-8-Text.",
+        ),
+        ("Added module {name}", "Modul {name} hinzugefügt"),
+        ("Replaced module {name}", "Modul {name} ersetzt"),
+        ("Removed module {name}", "Modul {name} entfernt"),
+        ("Import it with", "Importiere es mit"),
+        ("Unknown module", "Unbekanntes Modul"),
+        (
+            "There is no module `{name}` on this server.",
+            "Auf diesem Server gibt es kein Modul `{name}`.",
+        ),
+        (
+            "There are no modules yet, upload some with `/module add`.",
+            "Es gibt noch keine Module, lade welche mit `/modul hinzufügen` hoch.",
+        ),
+        // Snippets
+        ("Snippet {name}", "Snippet {name}"),
+        ("{name} (server)", "{name} (Server)"),
+        ("Invalid snippet name", "Ungültiger Snippet-Name"),
+        (
+            "`{name}` is not a valid name, use at most {length} characters.",
+            "`{name}` ist kein gültiger Name, verwende höchstens {length} Zeichen.",
+        ),
+        (
+            "Saved snippet {name} in {library}",
+            "Snippet {name} in {library} gespeichert",
+        ),
+        (
+            "Replaced snippet {name} in {library}",
+            "Snippet {name} in {library} ersetzt",
+        ),
+        (
+            "Removed snippet {name} from {library}",
+            "Snippet {name} aus {library} entfernt",
+        ),
+        (
+            "Render it with `/snippet use name:{name}`",
+            "Rendere es mit `/snippet verwenden name:{name}`",
+        ),
+        ("Nothing to save", "Nichts zu speichern"),
+        (
+            "Reply to the message with the code you want to save.",
+            "Antworte auf die Nachricht mit dem Code, den du speichern willst.",
+        ),
+        ("Several code blocks", "Mehrere Codeblöcke"),
+        (
+            "A snippet can only hold a single code block.",
+            "Ein Snippet kann nur einen einzigen Codeblock enthalten.",
+        ),
+        ("Unknown snippet", "Unbekanntes Snippet"),
+        (
+            "There is no snippet `{name}`, save one with `/snippet save`.",
+            "Es gibt kein Snippet `{name}`, speichere eines mit `/snippet speichern`.",
+        ),
+        (
+            "There is no snippet `{name}` in {library}.",
+            "In {library} gibt es kein Snippet `{name}`.",
+        ),
+        // Themes
+        ("Your theme", "Dein Design"),
+        ("Updated your theme", "Dein Design wurde geändert"),
+        ("{theme}, transparent", "{theme}, transparent"),
+        // History
+        ("No renders found", "Keine Bilder gefunden"),
+        (
+            "None of your renders contain `{search}`.",
+            "Keines deiner Bilder enthält `{search}`.",
+        ),
+        (
+            "Your renders show up here once you rendered something.",
+            "Deine Bilder erscheinen hier, sobald du etwas gerendert hast.",
+        ),
+        ("Jump to the render", "Zum Bild springen"),
+        (
+            "{engine} render {index} of {total}",
+            "{engine}-Bild {index} von {total}",
+        ),
+        ("Newer", "Neuer"),
+        ("Older", "Älter"),
+        ("Post", "Posten"),
+        ("Render again", "Erneut rendern"),
+        (
+            "The render is gone, but you can still render it again.",
+            "Das Bild ist nicht mehr da, aber du kannst es erneut rendern.",
+        ),
+        // Context menu entries
+        ("Render math", "Mathe rendern"),
+        // Command descriptions
+        (
+            "Render LaTeX code entered in a text box",
+            "LaTeX-Code aus einem Textfeld rendern",
+        ),
+        (
+            "Render typst code entered in a text box",
+            "typst-Code aus einem Textfeld rendern",
+        ),
+        (
+            "Render LaTeX or typst code entered in a text box, picking every detail of the image",
+            "LaTeX- oder typst-Code aus einem Textfeld rendern und jedes Detail des Bildes wählen",
+        ),
+        (
+            "Engine, guessed from the code if empty",
+            "Engine, wird aus dem Code geraten wenn leer",
+        ),
+        (
+            "Page width in centimetres, defaults to the one of this channel",
+            "Seitenbreite in Zentimetern, standardmäßig die dieses Kanals",
+        ),
+        (
+            "Resolution in dots per inch, defaults to 300",
+            "Auflösung in Punkten pro Zoll, standardmäßig 300",
+        ),
+        (
+            "File format of the image, defaults to PNG",
+            "Dateiformat des Bildes, standardmäßig PNG",
+        ),
+        (
+            "Hide the image behind a spoiler",
+            "Das Bild hinter einem Spoiler verstecken",
+        ),
+        (
+            "Colors of the image, defaults to your /theme",
+            "Farben des Bildes, standardmäßig dein /design",
+        ),
+        ("Leave out the background", "Hintergrund weglassen"),
+        ("Show full response", "Vollständige Antwort zeigen"),
+        ("Query", "Anfrage"),
+        (
+            "Configure the bot for this server or a single channel",
+            "Den Bot für diesen Server oder einen einzelnen Kanal einstellen",
+        ),
+        (
+            "Show the settings of this server and channel",
+            "Einstellungen dieses Servers und Kanals anzeigen",
+        ),
+        (
+            "Set the engine used when none is picked explicitly",
+            "Engine festlegen, die verwendet wird, wenn keine ausgewählt ist",
+        ),
+        (
+            "Default engine, leave empty to inherit",
+            "Standard-Engine, leer lassen zum Erben",
+        ),
+        ("Only change this channel", "Nur diesen Kanal ändern"),
+        (
+            "Set the width renders start out with",
+            "Breite festlegen, mit der Bilder anfangen",
+        ),
+        (
+            "Default width, leave empty to inherit",
+            "Standardbreite, leer lassen zum Erben",
+        ),
+        (
+            "Render math in messages automatically",
+            "Mathe in Nachrichten automatisch rendern",
+        ),
+        (
+            "Whether to render automatically, leave empty to inherit",
+            "Ob automatisch gerendert wird, leer lassen zum Erben",
+        ),
+        (
+            "Set a role that may delete and resize renders of others, besides members who can \
+             manage messages",
+            "Rolle, die fremde Bilder löschen und skalieren darf, zusätzlich zu \
+             Nachrichtenverwaltern",
+        ),
+        (
+            "Moderator role, leave empty to inherit",
+            "Moderatorrolle, leer lassen zum Erben",
+        ),
+        (
+            "Answer in one language instead of the one each user picked in Discord",
+            "In einer Sprache antworten statt in der, die jeder in Discord gewählt hat",
+        ),
+        (
+            "Language of responses, leave empty to inherit",
+            "Sprache der Antworten, leer lassen zum Erben",
+        ),
+        (
+            "Render a message when someone reacts to it with an emoji",
+            "Eine Nachricht rendern, wenn jemand mit einem Emoji darauf reagiert",
+        ),
+        (
+            "Emoji to react with, leave empty to inherit",
+            "Emoji zum Reagieren, leer lassen zum Erben",
+        ),
+        (
+            "Enable or disable a command",
+            "Einen Befehl aktivieren oder deaktivieren",
+        ),
+        ("Command to configure", "Einzustellender Befehl"),
+        (
+            "Whether the command can be used, leave empty to inherit",
+            "Ob der Befehl verwendet werden kann, leer lassen zum Erben",
+        ),
+        (
+            "Reset all settings to their inherited values",
+            "Alle Einstellungen auf die geerbten Werte zurücksetzen",
+        ),
+        ("Only reset this channel", "Nur diesen Kanal zurücksetzen"),
+        (
+            "Browse your past renders",
+            "Deine bisherigen Bilder durchsehen",
+        ),
+        (
+            "Only show renders whose source contains these words",
+            "Nur Bilder zeigen, deren Quelltext diese Wörter enthält",
+        ),
+        (
+            "Manage LaTeX macros that are available in all your renders",
+            "LaTeX-Makros verwalten, die in all deinen Bildern verfügbar sind",
+        ),
+        (
+            "Add a macro or replace one with the same name",
+            "Ein Makro hinzufügen oder eines mit gleichem Namen ersetzen",
+        ),
+        ("Name of the macro, e.g. \\R", "Name des Makros, z.B. \\R"),
+        (
+            "Definition, use #1, #2, ... to refer to arguments",
+            "Definition, Argumente mit #1, #2, ... verwenden",
+        ),
+        (
+            "Number of arguments, guessed from the definition if empty",
+            "Anzahl der Argumente, aus der Definition geraten wenn leer",
+        ),
+        (
+            "Add to the server library instead of yours",
+            "Zur Server-Bibliothek statt zu deiner hinzufügen",
+        ),
+        (
+            "List the macros available to you",
+            "Deine verfügbaren Makros auflisten",
+        ),
+        ("Remove a macro", "Ein Makro entfernen"),
+        (
+            "Remove from the server library instead of yours",
+            "Aus der Server-Bibliothek statt aus deiner entfernen",
+        ),
+        (
+            "Manage typst modules everybody on this server can import with \
+             `#import \"guild:name.typ\"`",
+            "typst-Module verwalten, die alle auf diesem Server mit \
+             `#import \"guild:name.typ\"` importieren können",
+        ),
+        (
+            "Upload a module or replace one with the same name",
+            "Ein Modul hochladen oder eines mit gleichem Namen ersetzen",
+        ),
+        (
+            "Name to import the module with, e.g. notation",
+            "Name, unter dem das Modul importiert wird, z.B. notation",
+        ),
+        ("The .typ file", "Die .typ-Datei"),
+        (
+            "List the modules of this server",
+            "Die Module dieses Servers auflisten",
+        ),
+        ("Remove a module", "Ein Modul entfernen"),
+        ("Name of the module", "Name des Moduls"),
+        (
+            "Save and render named snippets of LaTeX or typst code",
+            "Benannte Snippets aus LaTeX- oder typst-Code speichern und rendern",
+        ),
+        (
+            "Save code entered in a text box under a name, or the message you reply to with the \
+             prefix command",
+            "Code aus einem Textfeld unter einem Namen speichern, oder die mit dem Präfixbefehl \
+             beantwortete Nachricht",
+        ),
+        (
+            "Name to render the snippet with",
+            "Name, unter dem das Snippet gerendert wird",
+        ),
+        (
+            "Engine rendering the snippet, defaults to the one of this channel",
+            "Engine für das Snippet, standardmäßig die dieses Kanals",
+        ),
+        (
+            "Save to the server library instead of yours",
+            "In der Server-Bibliothek statt in deiner speichern",
+        ),
+        (
+            "Render a saved snippet",
+            "Ein gespeichertes Snippet rendern",
+        ),
+        ("Name of the snippet", "Name des Snippets"),
+        ("Remove a snippet", "Ein Snippet entfernen"),
+        (
+            "Pick the colors your renders use by default",
+            "Die Standardfarben deiner Bilder auswählen",
+        ),
+        (
+            "Palette, e.g. Light if you use Discord's light theme",
+            "Palette, z.B. Hell, wenn du Discords helles Design nutzt",
+        ),
+        (
+            "Leave out the background, so the image blends into any theme",
+            "Hintergrund weglassen, damit das Bild zu jedem Design passt",
+        ),
+        // Choices
+        ("Wide", "Breit"),
+        ("Widest", "Am breitesten"),
+        ("Dark", "Dunkel"),
+        ("Light", "Hell"),
+    ],
+    command_names: &[
+        ("config", "einstellungen"),
+        ("config show", "anzeigen"),
+        ("config width", "breite"),
+        ("config autorender", "automatisch"),
+        ("config moderators", "moderatoren"),
+        ("config language", "sprache"),
+        ("config reaction", "reaktion"),
+        ("config command", "befehl"),
+        ("config reset", "zurücksetzen"),
+        ("history", "verlauf"),
+        ("render", "rendern"),
+        ("macro", "makro"),
+        ("macro add", "hinzufügen"),
+        ("macro list", "liste"),
+        ("macro remove", "entfernen"),
+        ("module", "modul"),
+        ("module add", "hinzufügen"),
+        ("module list", "liste"),
+        ("module remove", "entfernen"),
+        ("snippet save", "speichern"),
+        ("snippet use", "verwenden"),
+        ("snippet remove", "entfernen"),
+        ("theme", "design"),
+    ],
+    parameter_names: &[
+        ("query", "anfrage"),
+        ("full_response", "vollständig"),
+        ("channel", "kanal"),
+        ("width", "breite"),
+        ("enabled", "aktiviert"),
+        ("role", "rolle"),
+        ("language", "sprache"),
+        ("search", "suche"),
+        ("arguments", "argumente"),
+        ("file", "datei"),
+        ("theme", "design"),
+    ],
+};
@@ -1,27 +1,38 @@
-use std::io::{Read, Write};
+use std::io::{Cursor, Read};
 
-use anyhow::bail;
-use log::{error, info};
+use anyhow::anyhow;
+use image::ImageReader;
+use log::info;
+use serde::{Deserialize, Serialize};
 
-use crate::docker::DockerCommand;
-use crate::{pdf, ImageWidth};
-
-fn image_width_measure(width: ImageWidth) -> &'static str {
-    match width {
-        ImageWidth::Wide => "18cm",
-        ImageWidth::Normal => "11.5cm",
-    }
-}
+use crate::docker::{self, DockerCommand, RendererOutput};
+use crate::pdf;
+use crate::render::{RenderRequest, MAX_HEIGHT_CM};
 
 pub struct RenderedLatex {
-    pub png: Vec<u8>,
+    pub image: Vec<u8>,
     pub overrun_hbox: bool,
+    pub too_tall: bool,
+}
+
+/// What the bot sends to the renderer on stdin.
+#[derive(Serialize, Deserialize)]
+struct LatexJob {
+    /// Extra definitions placed before `\begin{document}`, e.g. user macros.
+    preamble: String,
+    latex: String,
+    request: RenderRequest,
 }
 
-async fn render_to_png(width: ImageWidth, input: &str) -> anyhow::Result<RenderedLatex> {
+async fn render_to_image(job: &LatexJob) -> anyhow::Result<RenderedLatex> {
+    let request = &job.request;
+    // Without a page color the PDF, and thus the PNG, stays transparent
+    let background = match request.appearance.background() {
+        Some(color) => format!(r"\pagecolor[HTML]{{{color}}}"),
+        None => String::new(),
+    };
     let latex = r"
-        \documentclass[preview,border=2pt]{standalone}
-        \usepackage[paperwidth={{width}},paperheight=21cm,top=0mm,bottom=0mm,left=0mm,right=0mm]{geometry}
+        \documentclass[varwidth={{width}},border=2pt]{standalone}
         \usepackage{fontspec}
         \usepackage{amsmath,amssymb}
         \usepackage{xcolor}
@@ -31,86 +42,108 @@ async fn render_to_png(width: ImageWidth, input: &str) -> anyhow::Result<Rendere
 
         \setmathfont{Latin Modern Math}
 
-        \definecolor{discordbg}{HTML}{313338}
+        \definecolor{foreground}{HTML}{{{foreground}}}
+        {{preamble}}
 
         \begin{document}
-        \color{white}
-        \pagecolor{discordbg}
+        \color{foreground}
+        {{background}}
         {{input}}
         \end{document}
     "
-        .replace("{{input}}", input)
-        .replace("{{width}}", image_width_measure(width) );
+    .replace("{{foreground}}", request.appearance.foreground())
+    .replace("{{background}}", &background)
+    .replace("{{preamble}}", &job.preamble)
+    .replace("{{input}}", &job.latex)
+    .replace("{{width}}", &format!("{}cm", request.width_cm));
+
+    let pdf_result = pdf::render_pdf(&latex).await.map_err(|e| {
+        let message = e.to_string();
+        match already_defined(&message) {
+            Some(name) if job.preamble.contains(&format!(r"{{\{name}}}")) => anyhow!(
+                "{e}\nThe macro `\\{name}` from `/macro` is already defined by LaTeX or a package, \
+                 it needs another name."
+            ),
+            _ => e,
+        }
+    })?;
+    let png = pdf::pdf_to_png(pdf_result.pdf, request.dpi)?;
+    let (_, height) = ImageReader::new(Cursor::new(&png))
+        .with_guessed_format()?
+        .into_dimensions()?;
+    let height_cm = f64::from(height) / f64::from(request.dpi) * 2.54;
 
-    let pdf_result = pdf::render_pdf(&latex).await?;
     Ok(RenderedLatex {
-        png: pdf::pdf_to_png(pdf_result.pdf)?,
+        image: request.format.encode(png, request.appearance)?,
         overrun_hbox: pdf_result.overrun_hbox,
+        too_tall: height_cm > MAX_HEIGHT_CM,
     })
 }
 
-pub async fn run_renderer(width: ImageWidth) {
+/// The command a failed render tried to define again, without backslash.
+pub fn already_defined(error: &str) -> Option<&str> {
+    let (_, rest) = error.split_once(r"Command \")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(end);
+    (!name.is_empty() && rest.trim_start().starts_with("already defined")).then_some(name)
+}
+
+pub async fn run_renderer() {
     info!("Pivoting to tmp dir: {:?}", std::env::temp_dir());
     std::env::set_current_dir(std::env::temp_dir()).expect("could not change to tempdir");
 
-    let mut latex = String::new();
+    let mut job = String::new();
     std::io::stdin()
-        .read_to_string(&mut latex)
+        .read_to_string(&mut job)
         .expect("could not read stdin");
-
-    match render_to_png(width, &latex).await {
-        Ok(result) => {
-            std::io::stdout()
-                .write_all(&[0])
-                .expect("write error failed");
-            std::io::stdout()
-                .write_all(&[if result.overrun_hbox { 1 } else { 0 }])
-                .expect("write error failed");
-            std::io::stdout()
-                .write_all(&result.png)
-                .expect("could not write image");
-        }
-        Err(err) => {
-            std::io::stdout()
-                .write_all(&[1])
-                .expect("write error failed");
-            println!("{err}");
-        }
-    }
+    let job: LatexJob = serde_json::from_str(&job).expect("could not parse job");
+
+    let result = render_to_image(&job).await.map(|it| RendererOutput {
+        image: it.image,
+        overflow: it.overrun_hbox,
+        too_tall: it.too_tall,
+    });
+    docker::write_renderer_output(result);
 }
 
 pub async fn render_latex(
-    context_id: u64,
+    context_id: String,
     renderer_image: String,
     latex: String,
-    width: ImageWidth,
+    preamble: String,
+    request: &RenderRequest,
 ) -> anyhow::Result<RenderedLatex> {
+    let job = serde_json::to_string(&LatexJob {
+        preamble,
+        latex,
+        request: *request,
+    })?;
     let output = DockerCommand::new(renderer_image, format!("slave-latex-{context_id}"))
         .arg("render-latex")
-        .arg(width.arg_name())
-        .run(&latex)
+        .run(&job)
         .await?;
 
-    if output.stdout.len() < 3 {
-        error!(
-            "Renderer output too short with {}.\nStdout:{}\nStderr:\n{}",
-            output.status,
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        );
-        bail!("Renderer output not long enough");
-    }
+    let output = docker::read_renderer_output(&output)?;
+    Ok(RenderedLatex {
+        image: output.image,
+        overrun_hbox: output.overflow,
+        too_tall: output.too_tall,
+    })
+}
 
-    let error_bit = output.stdout[0];
-    if error_bit == 1 {
-        let stdout = String::from_utf8_lossy(&output.stdout.as_slice()[1..]);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        info!("Render failed:\nStdout:\n{stdout}\nStderr:\n{stderr}");
-        bail!("{}", stdout);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clashing_commands_are_named() {
+        let log = "! LaTeX Error: Command \\vec already defined.\n Or name \\end... illegal";
+        assert_eq!(already_defined(log), Some("vec"));
+        assert_eq!(
+            already_defined("! Undefined control sequence.\nl.3 \\vect"),
+            None
+        );
     }
-    let overflow_bit = output.stdout[1];
-    let overrun_hbox = overflow_bit != 0;
-    let png = output.stdout.as_slice()[2..].to_vec();
-
-    Ok(RenderedLatex { png, overrun_hbox })
 }
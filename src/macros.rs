@@ -0,0 +1,174 @@
+//! User and guild defined LaTeX macros, which are added to the preamble of every document.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    /// Name without the leading backslash.
+    pub name: String,
+    /// Number of arguments, at most 9.
+    pub arguments: u8,
+    pub body: String,
+}
+
+/// Why a macro was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidMacro {
+    Name,
+    TooManyArguments,
+    UnbalancedBraces,
+    /// LaTeX or one of its packages already has a command of that name. Only rendering the
+    /// definition tells.
+    Taken,
+}
+
+impl InvalidMacro {
+    /// Explanation for users, with the name of the macro as `{name}` placeholder.
+    pub fn message(self) -> &'static str {
+        match self {
+            InvalidMacro::Name => "Macro names may only consist of letters, but `{name}` does not.",
+            InvalidMacro::TooManyArguments => "LaTeX macros can take at most 9 arguments.",
+            InvalidMacro::UnbalancedBraces => {
+                "The braces in the body of `\\{name}` are not balanced."
+            }
+            InvalidMacro::Taken => {
+                "`\\{name}` is already defined by LaTeX or a package, pick another name."
+            }
+        }
+    }
+}
+
+impl Macro {
+    /// Creates a macro, accepting names with or without leading backslash.
+    pub fn new(name: &str, arguments: u8, body: String) -> Result<Self, InvalidMacro> {
+        let name = name.trim().trim_start_matches('\\');
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(InvalidMacro::Name);
+        }
+        if arguments > 9 {
+            return Err(InvalidMacro::TooManyArguments);
+        }
+        if !braces_balanced(&body) {
+            return Err(InvalidMacro::UnbalancedBraces);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            arguments,
+            body,
+        })
+    }
+
+    /// Guesses the number of arguments from the highest `#n` used in a body.
+    pub fn infer_arguments(body: &str) -> u8 {
+        body.as_bytes()
+            .windows(2)
+            .filter(|it| it[0] == b'#' && it[1].is_ascii_digit())
+            .map(|it| it[1] - b'0')
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn definition(&self) -> String {
+        if self.arguments == 0 {
+            format!(r"\newcommand{{\{}}}{{{}}}", self.name, self.body)
+        } else {
+            format!(
+                r"\newcommand{{\{}}}[{}]{{{}}}",
+                self.name, self.arguments, self.body
+            )
+        }
+    }
+}
+
+fn braces_balanced(body: &str) -> bool {
+    let mut depth = 0usize;
+    let mut escaped = false;
+
+    for c in body.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+
+    depth == 0
+}
+
+/// Combines user and guild macros, user macros win if both define the same name.
+pub fn merge(user: Vec<Macro>, guild: Vec<Macro>) -> Vec<Macro> {
+    let mut merged = guild
+        .into_iter()
+        .filter(|it| !user.iter().any(|user_macro| user_macro.name == it.name))
+        .collect::<Vec<_>>();
+    merged.extend(user);
+    merged
+}
+
+/// Builds the preamble defining all given macros.
+pub fn preamble(macros: &[Macro]) -> String {
+    macros
+        .iter()
+        .map(Macro::definition)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_macros_are_accepted() {
+        let vector = Macro::new(r" \vect ", 1, r"\mathbf{#1}".to_string()).unwrap();
+        assert_eq!(vector.name, "vect");
+        assert_eq!(vector.definition(), r"\newcommand{\vect}[1]{\mathbf{#1}}");
+
+        let reals = Macro::new("R", 0, r"\mathbb{R}".to_string()).unwrap();
+        assert_eq!(reals.definition(), r"\newcommand{\R}{\mathbb{R}}");
+    }
+
+    #[test]
+    fn invalid_macros_are_rejected() {
+        assert_eq!(Macro::new("", 0, String::new()), Err(InvalidMacro::Name));
+        assert_eq!(Macro::new(r"\", 0, String::new()), Err(InvalidMacro::Name));
+        assert_eq!(Macro::new("v2", 0, String::new()), Err(InvalidMacro::Name));
+        assert_eq!(
+            Macro::new("v", 10, String::new()),
+            Err(InvalidMacro::TooManyArguments)
+        );
+        assert_eq!(
+            Macro::new("v", 0, "{".to_string()),
+            Err(InvalidMacro::UnbalancedBraces)
+        );
+        assert_eq!(
+            Macro::new("v", 0, "}{".to_string()),
+            Err(InvalidMacro::UnbalancedBraces)
+        );
+        // Escaped braces do not count
+        assert!(Macro::new("v", 0, r"\{x".to_string()).is_ok());
+    }
+
+    #[test]
+    fn arguments_are_inferred() {
+        assert_eq!(Macro::infer_arguments("x"), 0);
+        assert_eq!(Macro::infer_arguments("#2 + #1"), 2);
+    }
+
+    #[test]
+    fn user_macros_win() {
+        let user = Macro::new("R", 0, "user".to_string()).unwrap();
+        let guild = Macro::new("R", 0, "guild".to_string()).unwrap();
+        let other = Macro::new("N", 0, "guild".to_string()).unwrap();
+
+        assert_eq!(
+            merge(vec![user.clone()], vec![guild, other.clone()]),
+            vec![other, user]
+        );
+    }
+}
@@ -1,36 +1,110 @@
-use clap::{Parser, Subcommand, ValueEnum};
-use log::warn;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use log::{info, warn};
 
 use crate::discord::BotContext;
+use crate::queue::RenderQueue;
+use crate::store::Store;
 use crate::wolframalpha::WolframAlpha;
 
+mod alt_text;
 mod discord;
 mod docker;
+mod i18n;
 mod latex;
+mod macros;
 mod pdf;
+mod preprocess;
+mod queue;
+mod render;
+mod settings;
+mod store;
+mod theme;
 mod typst;
 mod wolframalpha;
 
-#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+/// Preset page widths, renders can be switched between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
 enum ImageWidth {
-    Wide,
     Normal,
+    Wide,
+    Widest,
 }
 
 impl ImageWidth {
+    /// All presets, narrowest first.
+    const ALL: [ImageWidth; 3] = [ImageWidth::Normal, ImageWidth::Wide, ImageWidth::Widest];
+
     pub fn arg_name(self) -> &'static str {
         match self {
-            ImageWidth::Wide => "wide",
             ImageWidth::Normal => "normal",
+            ImageWidth::Wide => "wide",
+            ImageWidth::Widest => "widest",
+        }
+    }
+
+    pub fn from_arg_name(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(ImageWidth::Normal),
+            "wide" => Some(ImageWidth::Wide),
+            "widest" => Some(ImageWidth::Widest),
+            _ => None,
+        }
+    }
+
+    /// Largest page width the renderers may use, narrower content is shrink-wrapped.
+    pub fn centimetres(self) -> f64 {
+        match self {
+            ImageWidth::Normal => 11.5,
+            ImageWidth::Wide => 18.0,
+            ImageWidth::Widest => 25.0,
         }
     }
+
+    /// The narrowest preset that is wider than the given width.
+    pub fn wider_than(width_cm: f64) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.centimetres() > width_cm)
+    }
+
+    /// The widest preset that is narrower than the given width.
+    pub fn narrower_than(width_cm: f64) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .rev()
+            .find(|it| it.centimetres() < width_cm)
+    }
 }
 
 #[derive(Subcommand)]
 enum Command {
-    Bot { renderer_docker_image: String },
-    RenderLatex { width: ImageWidth },
+    Bot {
+        renderer_docker_image: String,
+        /// Path to the database persisting bot state across restarts
+        #[arg(long, default_value = "latexfogel.sqlite")]
+        database: PathBuf,
+        /// Number of days to remember rendered responses and the history of renders for
+        #[arg(long, default_value_t = 30)]
+        cache_ttl_days: u64,
+        /// Number of renderers allowed to run at the same time
+        #[arg(long, default_value_t = 2)]
+        max_concurrent_renders: usize,
+    },
+    /// Render the LaTeX job read from stdin, used inside the renderer image
+    RenderLatex,
+    /// Render the typst job read from stdin, used inside the renderer image
     RenderTypst,
+    /// Write the contents of the database as JSON to stdout
+    ExportStore {
+        #[arg(long, default_value = "latexfogel.sqlite")]
+        database: PathBuf,
+    },
+    /// Merge JSON previously written by `export-store` from stdin into the database
+    ImportStore {
+        #[arg(long, default_value = "latexfogel.sqlite")]
+        database: PathBuf,
+    },
 }
 
 #[derive(Parser)]
@@ -53,17 +127,55 @@ async fn main() {
     match args.command {
         Command::Bot {
             renderer_docker_image,
-        } => start_bot(renderer_docker_image).await,
-        Command::RenderLatex { width } => latex::run_renderer(width).await,
+            database,
+            cache_ttl_days,
+            max_concurrent_renders,
+        } => {
+            start_bot(
+                renderer_docker_image,
+                database,
+                cache_ttl_days,
+                max_concurrent_renders,
+            )
+            .await
+        }
+        Command::RenderLatex => latex::run_renderer().await,
         Command::RenderTypst => typst::run_renderer(),
+        Command::ExportStore { database } => export_store(database).await,
+        Command::ImportStore { database } => import_store(database).await,
     }
 }
 
-async fn start_bot(renderer_docker_image: String) {
+async fn start_bot(
+    renderer_docker_image: String,
+    database: PathBuf,
+    cache_ttl_days: u64,
+    max_concurrent_renders: usize,
+) {
+    let store = Store::open(&database).expect("Error opening database");
+
     discord::start_bot(BotContext::new(
         WolframAlpha::new(std::env::var("WOLFRAM_TOKEN").expect("missing WOLFRAM_TOKEN")),
         renderer_docker_image,
+        store,
+        Duration::from_secs(cache_ttl_days * 24 * 60 * 60),
+        RenderQueue::new(max_concurrent_renders),
     ))
     .await
     .expect("Error during bot startup");
 }
+
+async fn export_store(database: PathBuf) {
+    let store = Store::open(&database).expect("Error opening database");
+    let dump = store.export().await.expect("Error exporting database");
+
+    serde_json::to_writer_pretty(std::io::stdout(), &dump).expect("could not write dump");
+}
+
+async fn import_store(database: PathBuf) {
+    let store = Store::open(&database).expect("Error opening database");
+    let dump = serde_json::from_reader(std::io::stdin()).expect("could not parse dump");
+
+    store.import(dump).await.expect("Error importing database");
+    info!("Imported dump into {database:?}");
+}
@@ -41,7 +41,8 @@ pub async fn render_pdf(latex: &str) -> anyhow::Result<PdfResult> {
 
     bail!("**Unknown error**\n```{stderr}```");
 }
-pub fn pdf_to_png(pdf: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+/// Converts the first page of a PDF to a PNG with the given resolution in dots per inch.
+pub fn pdf_to_png(pdf: Vec<u8>, dpi: u32) -> anyhow::Result<Vec<u8>> {
     let dir = tempfile::tempdir()?;
     let pdf_path = dir.path().join("foo.pdf");
     let png_path = dir.path().join("foo.png");
@@ -49,7 +50,7 @@ pub fn pdf_to_png(pdf: Vec<u8>) -> anyhow::Result<Vec<u8>> {
     std::fs::write(&pdf_path, pdf)?;
     let out = Command::new("magick")
         .arg("-density")
-        .arg("300")
+        .arg(dpi.to_string())
         .arg(pdf_path.to_str().unwrap())
         .arg(png_path.to_str().unwrap())
         .output()?;
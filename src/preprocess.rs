@@ -0,0 +1,320 @@
+//! Inspection of message content before it is handed to a renderer.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::render::Engine;
+
+/// Checks whether a message contains math delimited the way the engine expects it.
+///
+/// LaTeX knows `$...$`, `$$...$$` and `\[...\]`, typst only `$...$`.
+pub fn contains_math(content: &str, engine: Engine) -> bool {
+    match engine {
+        Engine::Latex => {
+            contains_pair(content, "$$", "$$")
+                || contains_pair(content, r"\[", r"\]")
+                || contains_inline_math(content, true)
+        }
+        Engine::Typst => contains_inline_math(content, false),
+    }
+}
+
+/// Typst math functions that LaTeX spells as backslash commands.
+const TYPST_FUNCTIONS: &[&str] = &[
+    "abs",
+    "binom",
+    "cancel",
+    "cases",
+    "ceil",
+    "floor",
+    "frac",
+    "hat",
+    "lr",
+    "mat",
+    "norm",
+    "overline",
+    "root",
+    "sqrt",
+    "tilde",
+    "underline",
+    "vec",
+];
+
+/// Guesses which engine some source is written for, `None` if there is no telling.
+///
+/// LaTeX gives itself away by backslash commands like `\frac` and braces grouping scripts like
+/// `x^{2}`, typst by code like `#set`, parentheses grouping scripts like `sum_(i=1)^n` and
+/// function calls like `frac(a, b)`. Whichever engine collects more of these wins.
+pub fn detect_engine(content: &str) -> Option<Engine> {
+    let bytes = content.as_bytes();
+    let mut latex = 0;
+    let mut typst = 0;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_alphabetic() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            let word = &content[start..i];
+
+            match start.checked_sub(1).map(|it| bytes[it]) {
+                // Single letters after a backslash are typst escapes as well
+                Some(b'\\') if word.len() > 1 => latex += 2,
+                Some(b'\\') => {}
+                Some(b'#') => typst += 3,
+                _ if bytes.get(i) == Some(&b'(') && TYPST_FUNCTIONS.contains(&word) => typst += 2,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'^' | b'_', Some(b'{')) => latex += 2,
+            (b'^' | b'_', Some(b'(')) => typst += 2,
+            (b'$', Some(b'$')) => latex += 1,
+            (b'#', Some(b'(' | b'[')) => typst += 3,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    match latex.cmp(&typst) {
+        Ordering::Greater => Some(Engine::Latex),
+        Ordering::Less => Some(Engine::Typst),
+        Ordering::Equal => None,
+    }
+}
+
+fn contains_pair(content: &str, open: &str, close: &str) -> bool {
+    content
+        .find(open)
+        .is_some_and(|start| content[start + open.len()..].contains(close))
+}
+
+/// Looks for two consecutive, unescaped dollar signs enclosing some text.
+///
+/// In strict mode the math may not start or end with whitespace and may not be followed by a
+/// digit, so that "between $5 and $10" is not mistaken for math.
+fn contains_inline_math(content: &str, strict: bool) -> bool {
+    let bytes = content.as_bytes();
+    let dollars = (0..bytes.len())
+        .filter(|&i| bytes[i] == b'$' && (i == 0 || bytes[i - 1] != b'\\'))
+        .collect::<Vec<_>>();
+
+    dollars.windows(2).any(|pair| {
+        let (open, close) = (pair[0], pair[1]);
+        if close == open + 1 {
+            return false;
+        }
+        if !strict {
+            return true;
+        }
+
+        let after_open = bytes[open + 1];
+        let before_close = bytes[close - 1];
+        let after_close = bytes.get(close + 1);
+
+        !after_open.is_ascii_whitespace()
+            && !before_close.is_ascii_whitespace()
+            && !after_close.is_some_and(u8::is_ascii_digit)
+    })
+}
+
+/// A piece of a message that is rendered on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub engine: Engine,
+    pub source: String,
+}
+
+/// Splits a message into the parts to render.
+///
+/// Fenced code blocks tagged with a language we can render are rendered individually, ignoring
+/// all prose around them. Messages without such blocks are rendered as a whole with the fallback
+/// engine.
+pub fn parts(content: &str, fallback: Engine) -> Vec<Part> {
+    let blocks = code_blocks(content);
+    if blocks.is_empty() {
+        return vec![Part {
+            engine: fallback,
+            source: content.to_string(),
+        }];
+    }
+    blocks
+}
+
+/// Removes the markers of spoilers like `||secret||` from message content, returning whether
+/// there were any.
+///
+/// Only meant for Discord messages, in source typed anywhere else `||` is math like `$||x||$`.
+pub fn strip_spoilers(content: &str) -> (String, bool) {
+    let spoilers = spoiler_spans(content);
+    let mut stripped = String::with_capacity(content.len());
+    let mut end_of_last = 0;
+    for spoiler in &spoilers {
+        stripped.push_str(&content[end_of_last..spoiler.start]);
+        stripped.push_str(&content[spoiler.start + 2..spoiler.end - 2]);
+        end_of_last = spoiler.end;
+    }
+    stripped.push_str(&content[end_of_last..]);
+    (stripped, !spoilers.is_empty())
+}
+
+/// Byte ranges of spoiler markup, markers included. Like Discord, this ignores markers inside
+/// inline code and fenced code blocks.
+fn spoiler_spans(content: &str) -> Vec<Range<usize>> {
+    let mut markers = Vec::new();
+
+    let mut i = 0;
+    while i < content.len() {
+        let rest = &content[i..];
+        if rest.starts_with('`') {
+            // Code is closed by as many backticks as it was opened with, and without a closing
+            // run the backticks are just text
+            let run = rest.len() - rest.trim_start_matches('`').len();
+            i += run + rest[run..].find(&rest[..run]).map_or(0, |end| end + run);
+        } else if rest.starts_with("||") {
+            markers.push(i);
+            i += 2;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    markers
+        .chunks_exact(2)
+        .map(|pair| pair[0]..pair[1] + 2)
+        .collect()
+}
+
+/// Finds all fenced code blocks tagged `latex`, `tex`, `math` or `typst`.
+pub fn code_blocks(content: &str) -> Vec<Part> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("```") {
+        let after_fence = &rest[start + 3..];
+        let Some(end) = after_fence.find("```") else {
+            break;
+        };
+        let block = &after_fence[..end];
+        rest = &after_fence[end + 3..];
+
+        // The tag has to be on the same line as the opening fence
+        let Some((tag, source)) = block.split_once('\n') else {
+            continue;
+        };
+        let engine = match tag.trim().to_ascii_lowercase().as_str() {
+            "latex" | "tex" | "math" => Engine::Latex,
+            "typst" => Engine::Typst,
+            _ => continue,
+        };
+
+        blocks.push(Part {
+            engine,
+            source: source.to_string(),
+        });
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(engine: Engine, source: &str) -> Part {
+        Part {
+            engine,
+            source: source.to_string(),
+        }
+    }
+
+    #[test]
+    fn messages_without_blocks_are_one_part() {
+        assert_eq!(
+            parts("see $x^2$", Engine::Typst),
+            vec![part(Engine::Typst, "see $x^2$")]
+        );
+        // Blocks in other languages do not count
+        let rust = "```rust\nfn main() {}\n```";
+        assert_eq!(parts(rust, Engine::Latex), vec![part(Engine::Latex, rust)]);
+    }
+
+    #[test]
+    fn tagged_blocks_are_parts() {
+        let content = "first\n```tex\n\\frac{1}{2}\n```\nthen\n```Typst\n$x$\n```\n```py\n```";
+        assert_eq!(
+            parts(content, Engine::Latex),
+            vec![
+                part(Engine::Latex, "\\frac{1}{2}\n"),
+                part(Engine::Typst, "$x$\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_or_untagged_blocks_are_ignored() {
+        assert!(code_blocks("```latex\nx").is_empty());
+        assert!(code_blocks("```latex x```").is_empty());
+        assert_eq!(
+            code_blocks("```\nx\n``` and ```math\ny\n```"),
+            vec![part(Engine::Latex, "y\n")]
+        );
+    }
+
+    #[test]
+    fn engines_are_detected() {
+        assert_eq!(detect_engine(r"\frac{1}{2} + x^{2}"), Some(Engine::Latex));
+        assert_eq!(detect_engine(r"$$\int_0^1 x dx$$"), Some(Engine::Latex));
+        assert_eq!(
+            detect_engine("frac(1, 2) + sum_(i=1)^n i"),
+            Some(Engine::Typst)
+        );
+        assert_eq!(detect_engine("#set text(red)\n$x$"), Some(Engine::Typst));
+    }
+
+    #[test]
+    fn ambiguous_engines_are_not_guessed() {
+        assert_eq!(detect_engine("$x + y$"), None);
+        assert_eq!(detect_engine(""), None);
+        // Escaped letters are typst as much as LaTeX, and `frac` needs its parentheses
+        assert_eq!(detect_engine(r"\$ \# frac"), None);
+        // A tie is no telling either
+        assert_eq!(detect_engine(r"\alpha + sqrt(x)"), None);
+    }
+
+    #[test]
+    fn spoilers_are_stripped() {
+        assert_eq!(
+            strip_spoilers("||$x^2$|| and $y$"),
+            ("$x^2$ and $y$".to_string(), true)
+        );
+        assert_eq!(strip_spoilers("$x^2$"), ("$x^2$".to_string(), false));
+        // A lone marker is not a spoiler
+        assert_eq!(strip_spoilers("$a || b$"), ("$a || b$".to_string(), false));
+    }
+
+    #[test]
+    fn spoilers_ignore_code() {
+        let inline = "`||x||` and $y$";
+        assert_eq!(strip_spoilers(inline), (inline.to_string(), false));
+
+        let fenced = "```latex\n||x||\n```";
+        assert_eq!(strip_spoilers(fenced), (fenced.to_string(), false));
+
+        let hidden = "||```latex\nx\n```||";
+        assert_eq!(
+            strip_spoilers(hidden),
+            ("```latex\nx\n```".to_string(), true)
+        );
+
+        // Unclosed backticks are just text
+        assert_eq!(
+            strip_spoilers("it`s ||$x$||"),
+            ("it`s $x$".to_string(), true)
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Limits how many renders run at once.
+//!
+//! Free slots are handed out round-robin between users, so a single user queueing many renders
+//! cannot starve everybody else.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Mutex;
+
+use poise::serenity_prelude::UserId;
+use tokio::select;
+use tokio::sync::watch;
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting for a slot, the position starts at 1.
+    Waiting(usize),
+    Running,
+    Cancelled,
+}
+
+struct Entry {
+    owner: UserId,
+    started: bool,
+    cancelled: bool,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: JobId,
+    jobs: HashMap<JobId, Entry>,
+    /// Users with waiting jobs, in the order they get the next free slot.
+    turns: VecDeque<UserId>,
+}
+
+impl State {
+    fn running(&self) -> usize {
+        self.jobs.values().filter(|job| job.started).count()
+    }
+
+    /// Waiting jobs of a user, oldest first.
+    fn waiting(&self, owner: UserId) -> Vec<JobId> {
+        let mut waiting = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.owner == owner && !job.started && !job.cancelled)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        waiting.sort_unstable();
+        waiting
+    }
+
+    fn status(&self, id: JobId) -> Status {
+        let Some(job) = self.jobs.get(&id) else {
+            return Status::Cancelled;
+        };
+        if job.cancelled {
+            return Status::Cancelled;
+        }
+        if job.started {
+            return Status::Running;
+        }
+
+        // Every user ahead of the owner in the rotation gets one more turn before the owner's
+        // k-th job starts, every user behind it gets as many turns.
+        let own = self.waiting(job.owner);
+        let k = own.iter().position(|it| *it == id).expect("job is waiting");
+        let mut ahead = k;
+        let mut before_owner = true;
+        for user in &self.turns {
+            if *user == job.owner {
+                before_owner = false;
+                continue;
+            }
+            let turns = if before_owner { k + 1 } else { k };
+            ahead += self.waiting(*user).len().min(turns);
+        }
+
+        Status::Waiting(ahead + 1)
+    }
+
+    /// Starts waiting jobs while there are free slots.
+    fn dispatch(&mut self, limit: usize) {
+        while self.running() < limit {
+            let Some(user) = self.turns.pop_front() else {
+                return;
+            };
+            let waiting = self.waiting(user);
+            let Some(next) = waiting.first() else {
+                continue;
+            };
+
+            self.jobs.get_mut(next).expect("job exists").started = true;
+            if waiting.len() > 1 {
+                self.turns.push_back(user);
+            }
+        }
+    }
+}
+
+pub struct RenderQueue {
+    limit: usize,
+    state: Mutex<State>,
+    /// Notified whenever a job starts, finishes or is cancelled, so waiting jobs can report
+    /// their new position.
+    changed: watch::Sender<()>,
+}
+
+impl RenderQueue {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            state: Mutex::new(State::default()),
+            changed: watch::Sender::new(()),
+        }
+    }
+
+    /// Adds a job to the queue. It leaves the queue again when the returned handle is dropped.
+    pub fn enter(&self, owner: UserId) -> Job<'_> {
+        let receiver = self.changed.subscribe();
+
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.jobs.insert(
+                id,
+                Entry {
+                    owner,
+                    started: false,
+                    cancelled: false,
+                },
+            );
+            if !state.turns.contains(&owner) {
+                state.turns.push_back(owner);
+            }
+            state.dispatch(self.limit);
+            id
+        };
+        self.changed.send_replace(());
+
+        Job {
+            queue: self,
+            id,
+            receiver,
+        }
+    }
+
+    /// User who queued a job, `None` if it already finished.
+    pub fn owner(&self, id: JobId) -> Option<UserId> {
+        self.state
+            .lock()
+            .unwrap()
+            .jobs
+            .get(&id)
+            .map(|job| job.owner)
+    }
+
+    /// Removes a waiting job from the queue or aborts a running one. Returns false if the job
+    /// already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            let Some(job) = state.jobs.get_mut(&id) else {
+                return false;
+            };
+            job.cancelled = true;
+            // Running jobs keep their slot until they actually stopped
+            state.dispatch(self.limit);
+        }
+        self.changed.send_replace(());
+        true
+    }
+
+    fn leave(&self, id: JobId) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.jobs.remove(&id);
+            state.dispatch(self.limit);
+        }
+        self.changed.send_replace(());
+    }
+}
+
+/// A job in the [`RenderQueue`].
+pub struct Job<'a> {
+    queue: &'a RenderQueue,
+    id: JobId,
+    receiver: watch::Receiver<()>,
+}
+
+impl Job<'_> {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn status(&self) -> Status {
+        self.queue.state.lock().unwrap().status(self.id)
+    }
+
+    /// Waits until the queue changed, the status might be the same afterwards.
+    pub async fn changed(&mut self) {
+        if self.receiver.changed().await.is_err() {
+            // The queue lives as long as its jobs, so this never happens
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Waits for a free slot and runs `work`. Returns `None` if the job was cancelled, in which
+    /// case `work` is dropped.
+    pub async fn run<F: Future>(mut self, work: F) -> Option<F::Output> {
+        loop {
+            match self.status() {
+                Status::Waiting(_) => self.changed().await,
+                Status::Running => break,
+                Status::Cancelled => return None,
+            }
+        }
+
+        tokio::pin!(work);
+        loop {
+            select! {
+                output = &mut work => return Some(output),
+                _ = self.changed() => {
+                    if self.status() == Status::Cancelled {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Job<'_> {
+    fn drop(&mut self) {
+        self.queue.leave(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: UserId = UserId::new(1);
+    const BOB: UserId = UserId::new(2);
+
+    #[test]
+    fn slots_alternate_between_users() {
+        let queue = RenderQueue::new(1);
+        let first = queue.enter(ALICE);
+        let second = queue.enter(ALICE);
+        let third = queue.enter(ALICE);
+        let bob = queue.enter(BOB);
+
+        assert_eq!(first.status(), Status::Running);
+        assert_eq!(second.status(), Status::Waiting(1));
+        assert_eq!(bob.status(), Status::Waiting(2));
+        assert_eq!(third.status(), Status::Waiting(3));
+
+        drop(first);
+        assert_eq!(second.status(), Status::Running);
+        assert_eq!(bob.status(), Status::Waiting(1));
+        assert_eq!(third.status(), Status::Waiting(2));
+
+        drop(second);
+        assert_eq!(bob.status(), Status::Running);
+        assert_eq!(third.status(), Status::Waiting(1));
+
+        drop(bob);
+        assert_eq!(third.status(), Status::Running);
+    }
+
+    #[test]
+    fn cancelled_jobs_give_up_their_place() {
+        let queue = RenderQueue::new(1);
+        let running = queue.enter(ALICE);
+        let cancelled = queue.enter(BOB);
+        let waiting = queue.enter(BOB);
+
+        assert_eq!(waiting.status(), Status::Waiting(2));
+        assert!(queue.cancel(cancelled.id()));
+        assert_eq!(cancelled.status(), Status::Cancelled);
+        assert_eq!(waiting.status(), Status::Waiting(1));
+
+        // A cancelled running job keeps its slot until it stopped
+        assert!(queue.cancel(running.id()));
+        assert_eq!(waiting.status(), Status::Waiting(1));
+        drop(running);
+        assert_eq!(waiting.status(), Status::Running);
+
+        let id = cancelled.id();
+        drop(cancelled);
+        assert_eq!(queue.owner(id), None);
+        assert!(!queue.cancel(id));
+    }
+}
@@ -0,0 +1,264 @@
+//! Engine-agnostic entry point for rendering user input.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::alt_text::{self, AltText};
+use crate::theme::Appearance;
+use crate::{latex, preprocess, typst};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Engine {
+    #[name = "LaTeX"]
+    Latex,
+    #[name = "typst"]
+    Typst,
+}
+
+impl Engine {
+    /// Human readable name of the engine.
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Engine::Latex => "LaTeX",
+            Engine::Typst => "typst",
+        }
+    }
+
+    /// Stable identifier, used when persisting the engine.
+    pub fn key(self) -> &'static str {
+        match self {
+            Engine::Latex => "latex",
+            Engine::Typst => "typst",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "latex" => Some(Engine::Latex),
+            "typst" => Some(Engine::Typst),
+            _ => None,
+        }
+    }
+
+    /// The engine to try when a render with this one failed.
+    pub fn other(self) -> Self {
+        match self {
+            Engine::Latex => Engine::Typst,
+            Engine::Typst => Engine::Latex,
+        }
+    }
+
+    /// File extension of source files, also used to highlight code blocks.
+    pub fn source_extension(self) -> &'static str {
+        match self {
+            Engine::Latex => "tex",
+            Engine::Typst => "typ",
+        }
+    }
+}
+
+/// File format of rendered images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum OutputFormat {
+    #[name = "PNG"]
+    Png,
+    #[name = "JPEG"]
+    Jpeg,
+    #[name = "WebP"]
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    /// Converts the PNG a renderer produced. JPEG cannot be transparent, so transparent pixels
+    /// get the background color of the theme.
+    pub fn encode(self, png: Vec<u8>, appearance: Appearance) -> anyhow::Result<Vec<u8>> {
+        let format = match self {
+            OutputFormat::Png => return Ok(png),
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+        };
+
+        let mut image = image::load_from_memory_with_format(&png, ImageFormat::Png)?;
+        if self == OutputFormat::Jpeg {
+            image = DynamicImage::ImageRgb8(flatten(&image, appearance.theme.background()));
+        }
+
+        let mut encoded = Vec::new();
+        image.write_to(&mut Cursor::new(&mut encoded), format)?;
+        Ok(encoded)
+    }
+}
+
+/// Blends an image onto a background color given as hex code without `#`.
+fn flatten(image: &DynamicImage, background: &str) -> RgbImage {
+    let channel = |idx: usize| u8::from_str_radix(&background[idx..idx + 2], 16).unwrap_or(0);
+    let background = [channel(0), channel(2), channel(4)];
+
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |fg: u8, bg: u8| {
+            ((u16::from(fg) * u16::from(a) + u16::from(bg) * u16::from(255 - a)) / 255) as u8
+        };
+        Rgb([
+            blend(r, background[0]),
+            blend(g, background[1]),
+            blend(b, background[2]),
+        ])
+    })
+}
+
+/// Resolution of renders unless picked otherwise, in dots per inch.
+pub const DEFAULT_DPI: u32 = 300;
+
+/// How some source is rendered, everything besides the source and the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RenderRequest {
+    pub appearance: Appearance,
+    /// Maximum page width, longer lines are wrapped.
+    pub width_cm: f64,
+    /// Resolution of the image in dots per inch.
+    pub dpi: u32,
+    pub format: OutputFormat,
+    /// Whether Discord hides the image until it is clicked.
+    pub spoiler: bool,
+}
+
+impl RenderRequest {
+    /// A PNG at the default resolution.
+    pub fn new(appearance: Appearance, width_cm: f64) -> Self {
+        Self {
+            appearance,
+            width_cm,
+            dpi: DEFAULT_DPI,
+            format: OutputFormat::Png,
+            spoiler: false,
+        }
+    }
+}
+
+pub struct Rendered {
+    /// The encoded image, in the format that was requested.
+    pub image: Vec<u8>,
+    pub format: OutputFormat,
+    /// Whether the content did not fit and a wider render might look better.
+    pub overrun_hbox: bool,
+    /// Whether the image is taller than [`MAX_HEIGHT_CM`].
+    pub too_tall: bool,
+}
+
+/// Range of page widths users may pick.
+pub const MIN_WIDTH_CM: f64 = 3.0;
+pub const MAX_WIDTH_CM: f64 = 50.0;
+
+/// Renders taller than this are flagged, as they are hard to read in a chat.
+pub const MAX_HEIGHT_CM: f64 = 21.0;
+
+/// Discord allows at most 10 attachments per message.
+const MAX_PARTS: usize = 10;
+
+/// A rendered part of some input, see [`preprocess::parts`].
+pub struct RenderedPart {
+    pub engine: Engine,
+    pub result: anyhow::Result<Rendered>,
+    /// Text version of the source, for screen readers.
+    pub alt_text: AltText,
+    /// Whether Discord hides the image until it is clicked.
+    pub spoiler: bool,
+}
+
+/// Definitions made available to documents by users and guilds.
+#[derive(Debug, Clone, Default)]
+pub struct Libraries {
+    /// Macro definitions for the LaTeX preamble.
+    pub latex_preamble: String,
+    /// Typst modules by name.
+    pub typst_modules: BTreeMap<String, String>,
+}
+
+/// Renders all parts of an input one after another.
+pub async fn render_input(
+    context_id: u64,
+    renderer_image: &str,
+    fallback: Engine,
+    input: &str,
+    libraries: &Libraries,
+    request: &RenderRequest,
+) -> Vec<RenderedPart> {
+    let mut rendered = Vec::new();
+
+    let parts = preprocess::parts(input, fallback);
+    for (idx, part) in parts.into_iter().take(MAX_PARTS).enumerate() {
+        let alt_text = alt_text::convert(&part.source, part.engine);
+        let result = render(
+            format!("{context_id}-{idx}"),
+            renderer_image.to_string(),
+            part.engine,
+            part.source,
+            libraries,
+            request,
+        )
+        .await;
+
+        rendered.push(RenderedPart {
+            engine: part.engine,
+            result,
+            alt_text,
+            spoiler: request.spoiler,
+        });
+    }
+
+    rendered
+}
+
+/// Whether any part did not fit and could be rendered wider.
+pub fn can_widen(parts: &[RenderedPart]) -> bool {
+    parts
+        .iter()
+        .any(|part| part.result.as_ref().is_ok_and(|it| it.overrun_hbox))
+}
+
+pub async fn render(
+    context_id: String,
+    renderer_image: String,
+    engine: Engine,
+    source: String,
+    libraries: &Libraries,
+    request: &RenderRequest,
+) -> anyhow::Result<Rendered> {
+    match engine {
+        Engine::Latex => {
+            let preamble = libraries.latex_preamble.clone();
+            let image =
+                latex::render_latex(context_id, renderer_image, source, preamble, request).await?;
+            Ok(Rendered {
+                image: image.image,
+                format: request.format,
+                overrun_hbox: image.overrun_hbox,
+                too_tall: image.too_tall,
+            })
+        }
+        Engine::Typst => {
+            let modules = libraries.typst_modules.clone();
+            let image =
+                typst::render_typst(context_id, renderer_image, source, modules, request).await?;
+            Ok(Rendered {
+                image: image.image,
+                format: request.format,
+                overrun_hbox: image.overflow,
+                too_tall: image.too_tall,
+            })
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-guild and per-channel settings.
+//!
+//! Settings are stored per scope, which is either a guild or a channel. A channel inherits every
+//! value it does not set itself from its guild, which in turn falls back to the defaults below.
+
+use poise::serenity_prelude::{ChannelId, GuildId, ReactionType, RoleId};
+
+use crate::i18n::Locale;
+use crate::render::Engine;
+use crate::ImageWidth;
+
+/// What settings are stored for. Guilds and channels are kept apart, as the default channel of
+/// older guilds has the same id as the guild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Guild(GuildId),
+    Channel(ChannelId),
+}
+
+impl Scope {
+    /// Stored along with the id.
+    pub fn kind(self) -> &'static str {
+        match self {
+            Scope::Guild(_) => "guild",
+            Scope::Channel(_) => "channel",
+        }
+    }
+
+    pub fn id(self) -> u64 {
+        match self {
+            Scope::Guild(id) => id.get(),
+            Scope::Channel(id) => id.get(),
+        }
+    }
+}
+
+/// Settings of a single scope. `None` values are inherited.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Engine used when the user does not pick one explicitly.
+    pub default_engine: Option<Engine>,
+    /// Page width renders start out with.
+    pub default_width: Option<ImageWidth>,
+    /// Whether math in messages is rendered without being asked to.
+    pub auto_render: Option<bool>,
+    /// Members with this role may delete and resize renders of others.
+    pub moderator_role: Option<RoleId>,
+    /// Language of responses, overriding the language users picked in Discord.
+    pub locale: Option<Locale>,
+    /// Reacting to a message with this emoji renders it.
+    pub render_reaction: Option<ReactionType>,
+}
+
+/// Settings with all inheritance resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectiveSettings {
+    pub default_engine: Engine,
+    pub default_width: ImageWidth,
+    pub auto_render: bool,
+    pub moderator_role: Option<RoleId>,
+    /// `None` answers everyone in their own language.
+    pub locale: Option<Locale>,
+    /// `None` disables rendering on reactions.
+    pub render_reaction: Option<ReactionType>,
+}
+
+impl EffectiveSettings {
+    /// Resolves settings from the most specific to the least specific scope.
+    pub fn resolve(scopes: &[Settings]) -> Self {
+        Self {
+            default_engine: scopes
+                .iter()
+                .find_map(|it| it.default_engine)
+                .unwrap_or(Engine::Latex),
+            default_width: scopes
+                .iter()
+                .find_map(|it| it.default_width)
+                .unwrap_or(ImageWidth::Normal),
+            auto_render: scopes.iter().find_map(|it| it.auto_render).unwrap_or(false),
+            moderator_role: scopes.iter().find_map(|it| it.moderator_role),
+            locale: scopes.iter().find_map(|it| it.locale),
+            render_reaction: scopes.iter().find_map(|it| it.render_reaction.clone()),
+        }
+    }
+}
+
+/// Resolves whether a command is enabled from per-scope overrides, most specific first.
+pub fn command_enabled(overrides: &[Option<bool>]) -> bool {
+    overrides.iter().find_map(|it| *it).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_inherit_from_guilds() {
+        let channel = Settings {
+            default_engine: Some(Engine::Typst),
+            auto_render: Some(false),
+            ..Settings::default()
+        };
+        let guild = Settings {
+            default_engine: Some(Engine::Latex),
+            default_width: Some(ImageWidth::Wide),
+            auto_render: Some(true),
+            locale: Some(Locale::German),
+            ..Settings::default()
+        };
+
+        let resolved = EffectiveSettings::resolve(&[channel, guild]);
+        assert_eq!(resolved.default_engine, Engine::Typst);
+        assert_eq!(resolved.default_width, ImageWidth::Wide);
+        assert!(!resolved.auto_render);
+        assert_eq!(resolved.locale, Some(Locale::German));
+        assert_eq!(resolved.moderator_role, None);
+    }
+
+    #[test]
+    fn nothing_set_resolves_to_defaults() {
+        let resolved = EffectiveSettings::resolve(&[Settings::default()]);
+        assert_eq!(
+            resolved,
+            EffectiveSettings {
+                default_engine: Engine::Latex,
+                default_width: ImageWidth::Normal,
+                auto_render: false,
+                moderator_role: None,
+                locale: None,
+                render_reaction: None,
+            }
+        );
+        assert_eq!(EffectiveSettings::resolve(&[]), resolved);
+    }
+
+    #[test]
+    fn commands_are_enabled_unless_overridden() {
+        assert!(command_enabled(&[]));
+        assert!(command_enabled(&[None, None]));
+        assert!(!command_enabled(&[None, Some(false)]));
+        assert!(command_enabled(&[Some(true), Some(false)]));
+        assert!(!command_enabled(&[Some(false), Some(true)]));
+    }
+
+    #[test]
+    fn scopes_are_told_apart() {
+        let guild = Scope::Guild(GuildId::new(7));
+        let channel = Scope::Channel(ChannelId::new(7));
+        assert_eq!(guild.id(), channel.id());
+        assert_ne!(guild.kind(), channel.kind());
+    }
+}
@@ -0,0 +1,1589 @@
+//! Persistent bot state, backed by an embedded SQLite database.
+//!
+//! The schema is versioned with SQLite's `user_version` pragma. New schema changes are appended
+//! to [`MIGRATIONS`] and applied in order when the store is opened. Never edit a migration that
+//! has already been released, add a new one instead.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use log::info;
+use poise::serenity_prelude::{ChannelId, GuildId, MessageId, ReactionType, RoleId, UserId};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::i18n::Locale;
+use crate::macros::Macro;
+use crate::render::{Engine, RenderRequest};
+use crate::settings::{Scope, Settings};
+use crate::theme::{Appearance, Theme};
+use crate::ImageWidth;
+
+/// Schema migrations, the n-th entry migrates from `user_version` n to n+1.
+const MIGRATIONS: &[&str] = &[
+    // 1: rendered responses and widen info
+    r"
+    CREATE TABLE rendered_responses (
+        message_id INTEGER PRIMARY KEY NOT NULL,
+        response_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        engine TEXT NOT NULL,
+        owner INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE widen_info (
+        response_id INTEGER PRIMARY KEY NOT NULL,
+        owner INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        engine TEXT NOT NULL,
+        request TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    ",
+    // 2: per-guild and per-channel settings. Scopes are told apart by their kind, as the default
+    // channel of older guilds has the id of the guild.
+    r"
+    CREATE TABLE settings (
+        scope_kind TEXT NOT NULL,
+        scope_id INTEGER NOT NULL,
+        default_engine TEXT,
+        default_width TEXT,
+        auto_render INTEGER,
+        moderator_role INTEGER,
+        locale TEXT,
+        render_reaction TEXT,
+        PRIMARY KEY (scope_kind, scope_id)
+    );
+    CREATE TABLE command_settings (
+        scope_kind TEXT NOT NULL,
+        scope_id INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        enabled INTEGER NOT NULL,
+        PRIMARY KEY (scope_kind, scope_id, command)
+    );
+    ",
+    // 3: LaTeX macro libraries of users and guilds
+    r"
+    CREATE TABLE macros (
+        scope_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        arguments INTEGER NOT NULL,
+        body TEXT NOT NULL,
+        PRIMARY KEY (scope_id, name)
+    );
+    ",
+    // 4: typst modules of guilds
+    r"
+    CREATE TABLE typst_modules (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        source TEXT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+    ",
+    // 5: personal default appearance of renders
+    r"
+    CREATE TABLE appearances (
+        user_id INTEGER PRIMARY KEY NOT NULL,
+        theme TEXT NOT NULL,
+        transparent INTEGER NOT NULL
+    );
+    ",
+    // 6: render history of users, searchable by source
+    r"
+    CREATE TABLE history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        user_id INTEGER NOT NULL,
+        engine TEXT NOT NULL,
+        source TEXT NOT NULL,
+        guild_id INTEGER,
+        channel_id INTEGER NOT NULL,
+        response_id INTEGER NOT NULL,
+        thumbnail BLOB,
+        request TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX history_by_user ON history (user_id, created_at);
+    CREATE VIRTUAL TABLE history_search USING fts5(
+        source,
+        content = 'history',
+        content_rowid = 'id'
+    );
+    CREATE TRIGGER history_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_search (rowid, source) VALUES (new.id, new.source);
+    END;
+    CREATE TRIGGER history_delete AFTER DELETE ON history BEGIN
+        INSERT INTO history_search (history_search, rowid, source)
+        VALUES ('delete', old.id, old.source);
+    END;
+    CREATE TRIGGER history_update AFTER UPDATE ON history BEGIN
+        INSERT INTO history_search (history_search, rowid, source)
+        VALUES ('delete', old.id, old.source);
+        INSERT INTO history_search (rowid, source) VALUES (new.id, new.source);
+    END;
+    ",
+    // 7: named snippets of users and guilds
+    r"
+    CREATE TABLE snippets (
+        scope_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        engine TEXT NOT NULL,
+        source TEXT NOT NULL,
+        PRIMARY KEY (scope_id, name)
+    );
+    ",
+    // 8: the LaTeX and typst context menus became one. It is configured like both were, if they
+    // were configured alike, and inherits otherwise.
+    r"
+    INSERT OR IGNORE INTO command_settings (scope_kind, scope_id, command, enabled)
+    SELECT scope_kind, scope_id, 'math_context_menu', MIN(enabled) FROM command_settings
+    WHERE command IN ('tex_context_menu', 'typst_context_menu')
+    GROUP BY scope_kind, scope_id
+    HAVING COUNT(*) = 2 AND MIN(enabled) = MAX(enabled);
+    DELETE FROM command_settings WHERE command IN ('tex_context_menu', 'typst_context_menu');
+    ",
+];
+
+/// How many renders are kept in the history of each user, older ones are dropped.
+const MAX_HISTORY_PER_USER: usize = 200;
+
+/// Parses a render request stored as JSON.
+fn request_column(row: &Row, idx: usize) -> rusqlite::Result<RenderRequest> {
+    let json = row.get::<_, String>(idx)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs database work on a blocking thread, as SQLite would hold up the async runtime.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let conn = self.conn.clone();
+        task::spawn_blocking(move || {
+            // A panic while holding the lock leaves no transaction open, rusqlite rolls it back
+            let mut conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+            work(&mut conn)
+        })
+        .await?
+    }
+
+    pub async fn rendered_response_id(
+        &self,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<MessageId>> {
+        let response_id = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT response_id FROM rendered_responses WHERE message_id = ?1",
+                        params![message_id.get()],
+                        |row| row.get::<_, u64>(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        Ok(response_id.map(MessageId::new))
+    }
+
+    /// Returns the response to a message, if we know enough about it to render it again.
+    pub async fn rendered_response(
+        &self,
+        message_id: MessageId,
+    ) -> anyhow::Result<Option<RenderedResponse>> {
+        let row = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT response_id, channel_id, engine, owner FROM rendered_responses
+                         WHERE message_id = ?1",
+                        params![message_id.get()],
+                        |row| {
+                            Ok((
+                                row.get::<_, u64>(0)?,
+                                row.get::<_, u64>(1)?,
+                                row.get::<_, String>(2)?,
+                                row.get::<_, u64>(3)?,
+                            ))
+                        },
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        let Some((response_id, channel_id, engine, owner)) = row else {
+            return Ok(None);
+        };
+        let Some(engine) = Engine::from_key(&engine) else {
+            bail!("Unknown engine {engine:?} stored for message {message_id}");
+        };
+
+        Ok(Some(RenderedResponse {
+            response_id: MessageId::new(response_id),
+            channel_id: ChannelId::new(channel_id),
+            engine,
+            owner: UserId::new(owner),
+        }))
+    }
+
+    pub async fn register_rendered_response(
+        &self,
+        message_id: MessageId,
+        response: RenderedResponse,
+    ) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "INSERT OR REPLACE INTO rendered_responses
+                 (message_id, response_id, created_at, channel_id, engine, owner)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    message_id.get(),
+                    response.response_id.get(),
+                    now(),
+                    response.channel_id.get(),
+                    response.engine.key(),
+                    response.owner.get()
+                ],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Changes the engine a response was rendered with, so edits of the message keep using it.
+    pub async fn set_rendered_engine(
+        &self,
+        response_id: MessageId,
+        engine: Engine,
+    ) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "UPDATE rendered_responses SET engine = ?2 WHERE response_id = ?1",
+                params![response_id.get(), engine.key()],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn forget_rendered_response(&self, message_id: MessageId) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "DELETE FROM rendered_responses WHERE message_id = ?1",
+                params![message_id.get()],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Stops a response from following edits and deletion of the message it was rendered from.
+    pub async fn detach_response(&self, response_id: MessageId) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "DELETE FROM rendered_responses WHERE response_id = ?1",
+                params![response_id.get()],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Forgets the widen info and history entry of a response that was deleted.
+    pub async fn forget_response(&self, response_id: MessageId) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM widen_info WHERE response_id = ?1",
+                params![response_id.get()],
+            )?;
+            tx.execute(
+                "DELETE FROM history WHERE response_id = ?1",
+                params![response_id.get()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn widen_info(&self, response_id: MessageId) -> anyhow::Result<Option<WidenInfo>> {
+        let row = self
+            .with_conn(move |conn| {
+                Ok(conn.query_row(
+                "SELECT owner, source, engine, request FROM widen_info WHERE response_id = ?1",
+                params![response_id.get()],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()?)
+            })
+            .await?;
+
+        let Some((owner, source, engine, request)) = row else {
+            return Ok(None);
+        };
+        let Some(engine) = Engine::from_key(&engine) else {
+            bail!("Unknown engine {engine:?} stored for response {response_id}");
+        };
+
+        Ok(Some(WidenInfo {
+            owner: UserId::new(owner),
+            engine,
+            source,
+            request: serde_json::from_str(&request)?,
+        }))
+    }
+
+    pub async fn register_widen_info(
+        &self,
+        response_id: MessageId,
+        info: WidenInfo,
+    ) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "INSERT OR REPLACE INTO widen_info
+                 (response_id, owner, source, engine, request, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    response_id.get(),
+                    info.owner.get(),
+                    info.source,
+                    info.engine.key(),
+                    serde_json::to_string(&info.request)?,
+                    now()
+                ],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Settings of a guild or channel.
+    pub async fn settings(&self, scope: Scope) -> anyhow::Result<Settings> {
+        let row = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT default_engine, default_width, auto_render, moderator_role, locale,
+                         render_reaction FROM settings WHERE scope_kind = ?1 AND scope_id = ?2",
+                        params![scope.kind(), scope.id()],
+                        |row| {
+                            Ok((
+                                row.get::<_, Option<String>>(0)?,
+                                row.get::<_, Option<String>>(1)?,
+                                row.get::<_, Option<bool>>(2)?,
+                                row.get::<_, Option<u64>>(3)?,
+                                row.get::<_, Option<String>>(4)?,
+                                row.get::<_, Option<String>>(5)?,
+                            ))
+                        },
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        let Some((default_engine, default_width, auto_render, moderator_role, locale, reaction)) =
+            row
+        else {
+            return Ok(Settings::default());
+        };
+
+        Ok(Settings {
+            default_engine: default_engine.as_deref().and_then(Engine::from_key),
+            default_width: default_width.as_deref().and_then(ImageWidth::from_arg_name),
+            auto_render,
+            moderator_role: moderator_role.map(RoleId::new),
+            locale: locale.as_deref().and_then(Locale::from_key),
+            render_reaction: reaction.and_then(|it| ReactionType::try_from(it).ok()),
+        })
+    }
+
+    pub async fn set_settings(&self, scope: Scope, settings: &Settings) -> anyhow::Result<()> {
+        let settings = settings.clone();
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "INSERT OR REPLACE INTO settings
+                 (scope_kind, scope_id, default_engine, default_width, auto_render, moderator_role,
+                  locale, render_reaction)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    scope.kind(),
+                    scope.id(),
+                    settings.default_engine.map(Engine::key),
+                    settings.default_width.map(ImageWidth::arg_name),
+                    settings.auto_render,
+                    settings.moderator_role.map(RoleId::get),
+                    settings.locale.map(Locale::key),
+                    settings
+                        .render_reaction
+                        .as_ref()
+                        .map(ReactionType::to_string)
+                ],
+            )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Whether a command was explicitly enabled or disabled in a guild or channel.
+    pub async fn command_enabled(
+        &self,
+        scope: Scope,
+        command: &str,
+    ) -> anyhow::Result<Option<bool>> {
+        let command = command.to_string();
+        let enabled = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT enabled FROM command_settings
+                         WHERE scope_kind = ?1 AND scope_id = ?2 AND command = ?3",
+                        params![scope.kind(), scope.id(), command],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        Ok(enabled)
+    }
+
+    /// All commands explicitly enabled or disabled in a guild or channel.
+    pub async fn command_overrides(&self, scope: Scope) -> anyhow::Result<Vec<(String, bool)>> {
+        let overrides = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .prepare(
+                        "SELECT command, enabled FROM command_settings
+                         WHERE scope_kind = ?1 AND scope_id = ?2 ORDER BY command",
+                    )?
+                    .query_map(params![scope.kind(), scope.id()], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<Result<_, _>>()?)
+            })
+            .await?;
+
+        Ok(overrides)
+    }
+
+    /// Enables or disables a command, `None` removes the override.
+    pub async fn set_command_enabled(
+        &self,
+        scope: Scope,
+        command: &str,
+        enabled: Option<bool>,
+    ) -> anyhow::Result<()> {
+        let command = command.to_string();
+        self.with_conn(move |conn| {
+            match enabled {
+                Some(enabled) => conn.execute(
+                    "INSERT OR REPLACE INTO command_settings
+                     (scope_kind, scope_id, command, enabled)
+                                         VALUES (?1, ?2, ?3, ?4)",
+                    params![scope.kind(), scope.id(), command, enabled],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM command_settings
+                     WHERE scope_kind = ?1 AND scope_id = ?2 AND command = ?3",
+                    params![scope.kind(), scope.id(), command],
+                )?,
+            };
+            Ok(())
+        })
+        .await
+    }
+
+    /// Macros of a user or guild, sorted by name.
+    pub async fn macros(&self, scope_id: u64) -> anyhow::Result<Vec<Macro>> {
+        self.with_conn(move |conn| {
+            let macros = conn
+                .prepare(
+                    "SELECT name, arguments, body FROM macros WHERE scope_id = ?1 ORDER BY name",
+                )?
+                .query_map(params![scope_id], |row| {
+                    Ok(Macro {
+                        name: row.get(0)?,
+                        arguments: row.get(1)?,
+                        body: row.get(2)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            Ok(macros)
+        })
+        .await
+    }
+
+    /// Adds a macro, replacing one with the same name. Returns the replaced macro.
+    pub async fn add_macro(&self, scope_id: u64, new: &Macro) -> anyhow::Result<Option<Macro>> {
+        let new = new.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            let old = tx
+                .query_row(
+                    "SELECT name, arguments, body FROM macros WHERE scope_id = ?1 AND name = ?2",
+                    params![scope_id, new.name],
+                    |row| {
+                        Ok(Macro {
+                            name: row.get(0)?,
+                            arguments: row.get(1)?,
+                            body: row.get(2)?,
+                        })
+                    },
+                )
+                .optional()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO macros (scope_id, name, arguments, body)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![scope_id, new.name, new.arguments, new.body],
+            )?;
+
+            tx.commit()?;
+            Ok(old)
+        })
+        .await
+    }
+
+    /// Removes a macro, returning whether it existed.
+    pub async fn remove_macro(&self, scope_id: u64, name: &str) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        let removed = self
+            .with_conn(move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM macros WHERE scope_id = ?1 AND name = ?2",
+                    params![scope_id, name],
+                )?)
+            })
+            .await?;
+        Ok(removed > 0)
+    }
+
+    /// Names of the snippets of a user or guild, sorted.
+    pub async fn snippet_names(&self, scope_id: u64) -> anyhow::Result<Vec<String>> {
+        let names = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .prepare("SELECT name FROM snippets WHERE scope_id = ?1 ORDER BY name")?
+                    .query_map(params![scope_id], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?)
+            })
+            .await?;
+
+        Ok(names)
+    }
+
+    pub async fn snippet(&self, scope_id: u64, name: &str) -> anyhow::Result<Option<Snippet>> {
+        let name = name.to_string();
+        let row = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT name, engine, source FROM snippets
+                         WHERE scope_id = ?1 AND name = ?2",
+                        params![scope_id, name],
+                        |row| {
+                            Ok((
+                                row.get::<_, String>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, String>(2)?,
+                            ))
+                        },
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        let Some((name, engine, source)) = row else {
+            return Ok(None);
+        };
+        let Some(engine) = Engine::from_key(&engine) else {
+            bail!("Unknown engine {engine:?} stored for snippet {name:?}");
+        };
+
+        Ok(Some(Snippet {
+            name,
+            engine,
+            source,
+        }))
+    }
+
+    /// Adds a snippet, replacing one with the same name. Returns whether one was replaced.
+    pub async fn add_snippet(&self, scope_id: u64, snippet: &Snippet) -> anyhow::Result<bool> {
+        let snippet = snippet.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            let replaced = tx.execute(
+                "DELETE FROM snippets WHERE scope_id = ?1 AND name = ?2",
+                params![scope_id, snippet.name],
+            )?;
+            tx.execute(
+                "INSERT INTO snippets (scope_id, name, engine, source) VALUES (?1, ?2, ?3, ?4)",
+                params![scope_id, snippet.name, snippet.engine.key(), snippet.source],
+            )?;
+
+            tx.commit()?;
+            Ok(replaced > 0)
+        })
+        .await
+    }
+
+    /// Removes a snippet, returning whether it existed.
+    pub async fn remove_snippet(&self, scope_id: u64, name: &str) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        let removed = self
+            .with_conn(move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM snippets WHERE scope_id = ?1 AND name = ?2",
+                    params![scope_id, name],
+                )?)
+            })
+            .await?;
+        Ok(removed > 0)
+    }
+
+    /// Names of all typst modules of a guild, sorted.
+    pub async fn typst_module_names(&self, guild_id: GuildId) -> anyhow::Result<Vec<String>> {
+        let names = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .prepare("SELECT name FROM typst_modules WHERE guild_id = ?1 ORDER BY name")?
+                    .query_map(params![guild_id.get()], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?)
+            })
+            .await?;
+
+        Ok(names)
+    }
+
+    /// All typst modules of a guild, by name.
+    pub async fn typst_modules(
+        &self,
+        guild_id: GuildId,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let modules = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .prepare("SELECT name, source FROM typst_modules WHERE guild_id = ?1")?
+                    .query_map(params![guild_id.get()], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<Result<_, _>>()?)
+            })
+            .await?;
+
+        Ok(modules)
+    }
+
+    /// Adds a typst module, replacing one with the same name. Returns whether one was replaced.
+    pub async fn add_typst_module(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        source: &str,
+    ) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        let source = source.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            let existed = tx
+                .query_row(
+                    "SELECT 1 FROM typst_modules WHERE guild_id = ?1 AND name = ?2",
+                    params![guild_id.get(), name],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            tx.execute(
+                "INSERT OR REPLACE INTO typst_modules (guild_id, name, source) VALUES (?1, ?2, ?3)",
+                params![guild_id.get(), name, source],
+            )?;
+
+            tx.commit()?;
+            Ok(existed)
+        })
+        .await
+    }
+
+    /// Removes a typst module, returning whether it existed.
+    pub async fn remove_typst_module(&self, guild_id: GuildId, name: &str) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        let removed = self
+            .with_conn(move |conn| {
+                Ok(conn.execute(
+                    "DELETE FROM typst_modules WHERE guild_id = ?1 AND name = ?2",
+                    params![guild_id.get(), name],
+                )?)
+            })
+            .await?;
+        Ok(removed > 0)
+    }
+
+    /// The default appearance a user picked, if any.
+    pub async fn appearance(&self, user_id: UserId) -> anyhow::Result<Option<Appearance>> {
+        let row = self
+            .with_conn(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT theme, transparent FROM appearances WHERE user_id = ?1",
+                        params![user_id.get()],
+                        |row| Ok((row.get::<_, String>(0)?, row.get(1)?)),
+                    )
+                    .optional()?)
+            })
+            .await?;
+
+        Ok(row.and_then(|(theme, transparent)| {
+            Some(Appearance {
+                theme: Theme::from_key(&theme)?,
+                transparent,
+            })
+        }))
+    }
+
+    pub async fn set_appearance(
+        &self,
+        user_id: UserId,
+        appearance: Appearance,
+    ) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+            "INSERT OR REPLACE INTO appearances (user_id, theme, transparent) VALUES (?1, ?2, ?3)",
+            params![
+                user_id.get(),
+                appearance.theme.key(),
+                appearance.transparent
+            ],
+        )?)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Adds a render to the history of its owner. Rendering the same source as the latest entry
+    /// again, e.g. when resizing, updates that entry instead.
+    pub async fn record_history(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let entry = entry.clone();
+        let request = serde_json::to_string(&entry.request)?;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            let latest = tx
+                .query_row(
+                    "SELECT id, engine, source FROM history WHERE user_id = ?1
+                     ORDER BY created_at DESC, id DESC LIMIT 1",
+                    params![entry.owner.get()],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            match latest {
+                Some((id, engine, source))
+                    if engine == entry.engine.key() && source == entry.source =>
+                {
+                    tx.execute(
+                        "UPDATE history SET guild_id = ?2, channel_id = ?3, response_id = ?4,
+                         thumbnail = ?5, created_at = ?6, request = ?7 WHERE id = ?1",
+                        params![
+                            id,
+                            entry.guild_id.map(GuildId::get),
+                            entry.channel_id.get(),
+                            entry.response_id.get(),
+                            entry.thumbnail,
+                            now(),
+                            request
+                        ],
+                    )?;
+                }
+                _ => {
+                    tx.execute(
+                        "INSERT INTO history
+                         (user_id, engine, source, guild_id, channel_id, response_id, thumbnail,
+                          created_at, request)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                        params![
+                            entry.owner.get(),
+                            entry.engine.key(),
+                            entry.source,
+                            entry.guild_id.map(GuildId::get),
+                            entry.channel_id.get(),
+                            entry.response_id.get(),
+                            entry.thumbnail,
+                            now(),
+                            request
+                        ],
+                    )?;
+                }
+            }
+
+            tx.execute(
+                "DELETE FROM history WHERE user_id = ?1 AND id NOT IN (
+                     SELECT id FROM history WHERE user_id = ?1
+                     ORDER BY created_at DESC, id DESC LIMIT ?2
+                 )",
+                params![entry.owner.get(), MAX_HISTORY_PER_USER],
+            )?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The entry at `index` in the history of a user, newest first, along with the number of
+    /// entries. Only entries whose source contains all words of `search` count, if given.
+    pub async fn history_entry(
+        &self,
+        user_id: UserId,
+        search: Option<&str>,
+        index: usize,
+    ) -> anyhow::Result<(Option<HistoryPage>, usize)> {
+        let search = search.map(str::to_string);
+        self.with_conn(move |conn| {
+            // FTS5 has its own query syntax, so every word is quoted and matched as a prefix
+            let query = search
+                .map(|search| {
+                    search
+                        .split(|c: char| !c.is_alphanumeric())
+                        .filter(|word| !word.is_empty())
+                        .map(|word| format!("\"{word}\"*"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .filter(|query| !query.is_empty());
+
+            let filter = if query.is_some() {
+                "user_id = ?1 AND id IN (
+                 SELECT rowid FROM history_search WHERE history_search MATCH ?2
+                 )"
+            } else {
+                "user_id = ?1 AND ?2 IS NULL"
+            };
+
+            let total = conn.query_row(
+                &format!("SELECT COUNT(*) FROM history WHERE {filter}"),
+                params![user_id.get(), query],
+                |row| row.get::<_, usize>(0),
+            )?;
+            let page = conn
+                .query_row(
+                    &format!(
+                        "SELECT engine, source, guild_id, channel_id, response_id, thumbnail,
+                         created_at, request FROM history WHERE {filter}
+                         ORDER BY created_at DESC, id DESC LIMIT 1 OFFSET ?3"
+                    ),
+                    params![user_id.get(), query, index],
+                    |row| {
+                        let engine = row.get::<_, String>(0)?;
+                        Ok(HistoryPage {
+                            entry: HistoryEntry {
+                                owner: user_id,
+                                engine: Engine::from_key(&engine).unwrap_or(Engine::Latex),
+                                source: row.get(1)?,
+                                guild_id: row.get::<_, Option<u64>>(2)?.map(GuildId::new),
+                                channel_id: ChannelId::new(row.get(3)?),
+                                response_id: MessageId::new(row.get(4)?),
+                                thumbnail: row.get(5)?,
+                                request: request_column(row, 7)?,
+                            },
+                            created_at: row.get(6)?,
+                        })
+                    },
+                )
+                .optional()?;
+
+            Ok((page, total))
+        })
+        .await
+    }
+
+    /// Deletes all cache entries and history older than `ttl`, returning how many rows were
+    /// removed.
+    pub async fn evict_older_than(&self, ttl: Duration) -> anyhow::Result<usize> {
+        let cutoff = now().saturating_sub(ttl.as_secs());
+        self.with_conn(move |conn| {
+            let mut removed = 0;
+            removed += conn.execute(
+                "DELETE FROM rendered_responses WHERE created_at < ?1",
+                params![cutoff],
+            )?;
+            removed += conn.execute(
+                "DELETE FROM widen_info WHERE created_at < ?1",
+                params![cutoff],
+            )?;
+            removed +=
+                conn.execute("DELETE FROM history WHERE created_at < ?1", params![cutoff])?;
+
+            Ok(removed)
+        })
+        .await
+    }
+
+    pub async fn export(&self) -> anyhow::Result<StoreDump> {
+        self.with_conn(move |conn| {
+            let rendered_responses = conn
+                .prepare(
+                    "SELECT message_id, response_id, created_at, channel_id, engine, owner
+                     FROM rendered_responses",
+                )?
+                .query_map([], |row| {
+                    Ok(RenderedResponseDump {
+                        message_id: row.get(0)?,
+                        response_id: row.get(1)?,
+                        created_at: row.get(2)?,
+                        channel_id: row.get(3)?,
+                        engine: row.get(4)?,
+                        owner: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let widen_info = conn
+                .prepare(
+                    "SELECT response_id, owner, source, engine, request, created_at
+                     FROM widen_info",
+                )?
+                .query_map([], |row| {
+                    Ok(WidenInfoDump {
+                        response_id: row.get(0)?,
+                        owner: row.get(1)?,
+                        source: row.get(2)?,
+                        engine: row.get(3)?,
+                        request: row.get(4)?,
+                        created_at: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let settings = conn
+                .prepare(
+                    "SELECT scope_kind, scope_id, default_engine, default_width, auto_render,
+                     moderator_role, locale, render_reaction FROM settings",
+                )?
+                .query_map([], |row| {
+                    Ok(SettingsDump {
+                        scope_kind: row.get(0)?,
+                        scope_id: row.get(1)?,
+                        default_engine: row.get(2)?,
+                        default_width: row.get(3)?,
+                        auto_render: row.get(4)?,
+                        moderator_role: row.get(5)?,
+                        locale: row.get(6)?,
+                        render_reaction: row.get(7)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let command_settings = conn
+                .prepare("SELECT scope_kind, scope_id, command, enabled FROM command_settings")?
+                .query_map([], |row| {
+                    Ok(CommandSettingDump {
+                        scope_kind: row.get(0)?,
+                        scope_id: row.get(1)?,
+                        command: row.get(2)?,
+                        enabled: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let macros = conn
+                .prepare("SELECT scope_id, name, arguments, body FROM macros")?
+                .query_map([], |row| {
+                    Ok(MacroDump {
+                        scope_id: row.get(0)?,
+                        name: row.get(1)?,
+                        arguments: row.get(2)?,
+                        body: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let typst_modules = conn
+                .prepare("SELECT guild_id, name, source FROM typst_modules")?
+                .query_map([], |row| {
+                    Ok(TypstModuleDump {
+                        guild_id: row.get(0)?,
+                        name: row.get(1)?,
+                        source: row.get(2)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let appearances = conn
+                .prepare("SELECT user_id, theme, transparent FROM appearances")?
+                .query_map([], |row| {
+                    Ok(AppearanceDump {
+                        user_id: row.get(0)?,
+                        theme: row.get(1)?,
+                        transparent: row.get(2)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let history = conn
+                .prepare(
+                    "SELECT id, user_id, engine, source, guild_id, channel_id, response_id,
+                     thumbnail, created_at, request FROM history",
+                )?
+                .query_map([], |row| {
+                    Ok(HistoryDump {
+                        id: row.get(0)?,
+                        user_id: row.get(1)?,
+                        engine: row.get(2)?,
+                        source: row.get(3)?,
+                        guild_id: row.get(4)?,
+                        channel_id: row.get(5)?,
+                        response_id: row.get(6)?,
+                        thumbnail: row.get(7)?,
+                        created_at: row.get(8)?,
+                        request: row.get(9)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            let snippets = conn
+                .prepare("SELECT scope_id, name, engine, source FROM snippets")?
+                .query_map([], |row| {
+                    Ok(SnippetDump {
+                        scope_id: row.get(0)?,
+                        name: row.get(1)?,
+                        engine: row.get(2)?,
+                        source: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            Ok(StoreDump {
+                schema_version: MIGRATIONS.len(),
+                rendered_responses,
+                widen_info,
+                settings,
+                command_settings,
+                macros,
+                typst_modules,
+                appearances,
+                history,
+                snippets,
+            })
+        })
+        .await
+    }
+
+    /// Merges a dump into the store. Existing rows with the same key are overwritten.
+    pub async fn import(&self, dump: StoreDump) -> anyhow::Result<()> {
+        if dump.schema_version > MIGRATIONS.len() {
+            bail!(
+                "Dump has schema version {}, but this build only knows {}",
+                dump.schema_version,
+                MIGRATIONS.len()
+            );
+        }
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            for row in dump.rendered_responses {
+                tx.execute(
+                    "INSERT OR REPLACE INTO rendered_responses
+                     (message_id, response_id, created_at, channel_id, engine, owner)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        row.message_id,
+                        row.response_id,
+                        row.created_at,
+                        row.channel_id,
+                        row.engine,
+                        row.owner
+                    ],
+                )?;
+            }
+            for row in dump.widen_info {
+                tx.execute(
+                    "INSERT OR REPLACE INTO widen_info
+                     (response_id, owner, source, engine, request, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        row.response_id,
+                        row.owner,
+                        row.source,
+                        row.engine,
+                        row.request,
+                        row.created_at
+                    ],
+                )?;
+            }
+            for row in dump.settings {
+                tx.execute(
+                    "INSERT OR REPLACE INTO settings
+                     (scope_kind, scope_id, default_engine, default_width, auto_render,
+                      moderator_role, locale, render_reaction)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        row.scope_kind,
+                        row.scope_id,
+                        row.default_engine,
+                        row.default_width,
+                        row.auto_render,
+                        row.moderator_role,
+                        row.locale,
+                        row.render_reaction
+                    ],
+                )?;
+            }
+            for row in dump.command_settings {
+                tx.execute(
+                    "INSERT OR REPLACE INTO command_settings
+                     (scope_kind, scope_id, command, enabled)
+                                         VALUES (?1, ?2, ?3, ?4)",
+                    params![row.scope_kind, row.scope_id, row.command, row.enabled],
+                )?;
+            }
+            for row in dump.macros {
+                tx.execute(
+                    "INSERT OR REPLACE INTO macros (scope_id, name, arguments, body)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![row.scope_id, row.name, row.arguments, row.body],
+                )?;
+            }
+            for row in dump.typst_modules {
+                tx.execute(
+                    "INSERT OR REPLACE INTO typst_modules (guild_id, name, source)
+                     VALUES (?1, ?2, ?3)",
+                    params![row.guild_id, row.name, row.source],
+                )?;
+            }
+            for row in dump.appearances {
+                tx.execute(
+                    "INSERT OR REPLACE INTO appearances (user_id, theme, transparent)
+                     VALUES (?1, ?2, ?3)",
+                    params![row.user_id, row.theme, row.transparent],
+                )?;
+            }
+            for row in dump.history {
+                // REPLACE would not run the delete trigger keeping the search index up to date
+                tx.execute("DELETE FROM history WHERE id = ?1", params![row.id])?;
+                tx.execute(
+                    "INSERT INTO history
+                     (id, user_id, engine, source, guild_id, channel_id, response_id, thumbnail,
+                      created_at, request)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        row.id,
+                        row.user_id,
+                        row.engine,
+                        row.source,
+                        row.guild_id,
+                        row.channel_id,
+                        row.response_id,
+                        row.thumbnail,
+                        row.created_at,
+                        row.request
+                    ],
+                )?;
+            }
+            for row in dump.snippets {
+                tx.execute(
+                    "INSERT OR REPLACE INTO snippets (scope_id, name, engine, source)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![row.scope_id, row.name, row.engine, row.source],
+                )?;
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version > MIGRATIONS.len() {
+        bail!(
+            "Database has schema version {version}, but this build only knows {}",
+            MIGRATIONS.len()
+        );
+    }
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("Migrating database to schema version {}", idx + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", idx + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RenderedResponse {
+    /// Our response (usually with image).
+    pub response_id: MessageId,
+    /// Channel of both the original message and our response.
+    pub channel_id: ChannelId,
+    /// Engine the original message was rendered with.
+    pub engine: Engine,
+    /// User who requested the rendering.
+    pub owner: UserId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WidenInfo {
+    /// Owner of the original message.
+    pub owner: UserId,
+    /// Engine the source was rendered with, tagged code blocks pick their own.
+    pub engine: Engine,
+    /// Code used to generate the original response.
+    pub source: String,
+    /// How the response is currently rendered, including its page width.
+    pub request: RenderRequest,
+}
+
+/// A render in the history of a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub owner: UserId,
+    pub engine: Engine,
+    pub source: String,
+    /// `None` in DMs.
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    /// Our response with the render.
+    pub response_id: MessageId,
+    /// PNG thumbnail of the first image of the render.
+    pub thumbnail: Option<Vec<u8>>,
+    pub request: RenderRequest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryPage {
+    pub entry: HistoryEntry,
+    /// Unix timestamp of the render.
+    pub created_at: u64,
+}
+
+/// Named source a user or guild saved to render it again later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub name: String,
+    pub engine: Engine,
+    pub source: String,
+}
+
+/// A portable snapshot of the whole store, used for export and import.
+#[derive(Serialize, Deserialize)]
+pub struct StoreDump {
+    pub schema_version: usize,
+    pub rendered_responses: Vec<RenderedResponseDump>,
+    pub widen_info: Vec<WidenInfoDump>,
+    pub settings: Vec<SettingsDump>,
+    pub command_settings: Vec<CommandSettingDump>,
+    pub macros: Vec<MacroDump>,
+    pub typst_modules: Vec<TypstModuleDump>,
+    pub appearances: Vec<AppearanceDump>,
+    pub history: Vec<HistoryDump>,
+    pub snippets: Vec<SnippetDump>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RenderedResponseDump {
+    pub message_id: u64,
+    pub response_id: u64,
+    pub created_at: u64,
+    pub channel_id: u64,
+    pub engine: String,
+    pub owner: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WidenInfoDump {
+    pub response_id: u64,
+    pub owner: u64,
+    pub source: String,
+    pub engine: String,
+    /// JSON of the render request.
+    pub request: String,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SettingsDump {
+    /// `guild` or `channel`.
+    pub scope_kind: String,
+    pub scope_id: u64,
+    pub default_engine: Option<String>,
+    pub default_width: Option<String>,
+    pub auto_render: Option<bool>,
+    pub moderator_role: Option<u64>,
+    pub locale: Option<String>,
+    pub render_reaction: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CommandSettingDump {
+    /// `guild` or `channel`.
+    pub scope_kind: String,
+    pub scope_id: u64,
+    pub command: String,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MacroDump {
+    pub scope_id: u64,
+    pub name: String,
+    pub arguments: u8,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TypstModuleDump {
+    pub guild_id: u64,
+    pub name: String,
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AppearanceDump {
+    pub user_id: u64,
+    pub theme: String,
+    pub transparent: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryDump {
+    pub id: i64,
+    pub user_id: u64,
+    pub engine: String,
+    pub source: String,
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub response_id: u64,
+    pub thumbnail: Option<Vec<u8>>,
+    pub created_at: u64,
+    /// JSON of the render request.
+    pub request: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnippetDump {
+    pub scope_id: u64,
+    pub name: String,
+    pub engine: String,
+    pub source: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a database at an older schema version, as an earlier build would have left it.
+    fn database_at(path: &Path, version: usize) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        for migration in &MIGRATIONS[..version] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+        conn
+    }
+
+    fn schema_version(store: &Store) -> usize {
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_database_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.sqlite");
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(schema_version(&store), MIGRATIONS.len());
+        drop(store);
+
+        // Opening a migrated database again changes nothing
+        let store = Store::open(&path).unwrap();
+        assert_eq!(schema_version(&store), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.sqlite");
+        database_at(&path, MIGRATIONS.len())
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(Store::open(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn context_menu_settings_are_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.sqlite");
+        database_at(&path, 7)
+            .execute_batch(
+                "INSERT INTO command_settings (scope_kind, scope_id, command, enabled) VALUES
+                 ('guild', 1, 'tex_context_menu', 0), ('guild', 1, 'typst_context_menu', 0),
+                 ('guild', 2, 'tex_context_menu', 0), ('guild', 2, 'typst_context_menu', 1),
+                 ('channel', 3, 'typst_context_menu', 0);",
+            )
+            .unwrap();
+
+        let store = Store::open(&path).unwrap();
+        let overrides = |id| store.command_overrides(Scope::Guild(GuildId::new(id)));
+        assert_eq!(
+            overrides(1).await.unwrap(),
+            vec![("math_context_menu".to_string(), false)]
+        );
+        assert_eq!(overrides(2).await.unwrap(), vec![]);
+        assert_eq!(
+            store
+                .command_overrides(Scope::Channel(ChannelId::new(3)))
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn guilds_and_channels_with_the_same_id_are_separate() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("store.sqlite")).unwrap();
+        let guild = Scope::Guild(GuildId::new(5));
+        let channel = Scope::Channel(ChannelId::new(5));
+
+        let typst = Settings {
+            default_engine: Some(Engine::Typst),
+            ..Settings::default()
+        };
+        store.set_settings(guild, &typst).await.unwrap();
+        store
+            .set_command_enabled(guild, "wolfram", Some(false))
+            .await
+            .unwrap();
+
+        assert_eq!(store.settings(guild).await.unwrap(), typst);
+        assert_eq!(store.settings(channel).await.unwrap(), Settings::default());
+        assert_eq!(
+            store.command_enabled(guild, "wolfram").await.unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            store.command_enabled(channel, "wolfram").await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn detached_responses_no_longer_follow_their_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("store.sqlite")).unwrap();
+        let response = RenderedResponse {
+            response_id: MessageId::new(2),
+            channel_id: ChannelId::new(3),
+            engine: Engine::Latex,
+            owner: UserId::new(4),
+        };
+        store
+            .register_rendered_response(MessageId::new(1), response)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.rendered_response(MessageId::new(1)).await.unwrap(),
+            Some(response)
+        );
+
+        store.detach_response(MessageId::new(2)).await.unwrap();
+        assert_eq!(
+            store.rendered_response(MessageId::new(1)).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn dumps_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("store.sqlite")).unwrap();
+        let info = WidenInfo {
+            owner: UserId::new(1),
+            engine: Engine::Typst,
+            source: "$x^2$".to_string(),
+            request: RenderRequest {
+                spoiler: true,
+                ..RenderRequest::new(Appearance::default(), ImageWidth::Wide.centimetres())
+            },
+        };
+        store
+            .register_widen_info(MessageId::new(9), info.clone())
+            .await
+            .unwrap();
+        let scope = Scope::Channel(ChannelId::new(3));
+        store
+            .set_command_enabled(scope, "tex", Some(false))
+            .await
+            .unwrap();
+
+        let copy = Store::open(&dir.path().join("copy.sqlite")).unwrap();
+        copy.import(store.export().await.unwrap()).await.unwrap();
+        assert_eq!(
+            copy.widen_info(MessageId::new(9)).await.unwrap(),
+            Some(info)
+        );
+        assert_eq!(
+            copy.command_enabled(scope, "tex").await.unwrap(),
+            Some(false)
+        );
+
+        copy.forget_response(MessageId::new(9)).await.unwrap();
+        assert_eq!(copy.widen_info(MessageId::new(9)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Colors of rendered images, shared by all engines.
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Locale;
+
+/// A named palette, matching one of Discord's themes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter)]
+pub enum Theme {
+    #[name = "Dark"]
+    Dark,
+    #[name = "Light"]
+    Light,
+    #[name = "AMOLED"]
+    Amoled,
+}
+
+impl Theme {
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+            Theme::Amoled => "AMOLED",
+        }
+    }
+
+    /// Stable identifier, used when persisting the theme.
+    pub fn key(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Amoled => "amoled",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            "amoled" => Some(Theme::Amoled),
+            _ => None,
+        }
+    }
+
+    /// Background color as hex code without `#`.
+    pub fn background(self) -> &'static str {
+        match self {
+            Theme::Dark => "313338",
+            Theme::Light => "FFFFFF",
+            Theme::Amoled => "000000",
+        }
+    }
+
+    /// Text color as hex code without `#`.
+    pub fn foreground(self) -> &'static str {
+        match self {
+            Theme::Dark | Theme::Amoled => "FFFFFF",
+            Theme::Light => "313338",
+        }
+    }
+}
+
+/// How a render looks: a palette and whether its background is left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Appearance {
+    pub theme: Theme,
+    /// Renders a transparent PNG, only the text color of the theme is used.
+    pub transparent: bool,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            transparent: false,
+        }
+    }
+}
+
+impl Appearance {
+    /// Background color as hex code without `#`, `None` if transparent.
+    pub fn background(self) -> Option<&'static str> {
+        (!self.transparent).then(|| self.theme.background())
+    }
+
+    pub fn foreground(self) -> &'static str {
+        self.theme.foreground()
+    }
+
+    pub fn describe(self, locale: Locale) -> String {
+        let theme = locale.tr(self.theme.display_name());
+        if self.transparent {
+            locale.fmt("{theme}, transparent", &[("theme", &theme)])
+        } else {
+            theme.to_string()
+        }
+    }
+}
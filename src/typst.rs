@@ -1,22 +1,26 @@
 use std::{
+    collections::BTreeMap,
     fs,
-    io::{ErrorKind, Read, Write},
+    io::{ErrorKind, Read},
     path::PathBuf,
     sync::OnceLock,
 };
 
 use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use typst::{
     diag::{FileError, FileResult},
     foundations::{Bytes, Datetime},
-    layout::Abs,
+    layout::{Abs, Frame, FrameItem, Point},
+    model::Document,
     syntax::{FileId, Source},
     text::{Font, FontBook, FontInfo},
     utils::LazyHash,
     Library, World,
 };
 
-use crate::docker::DockerCommand;
+use crate::docker::{self, DockerCommand, RendererOutput};
+use crate::render::{RenderRequest, MAX_HEIGHT_CM};
 
 // The logic for detecting and loading fonts was ripped straight from:
 // https://github.com/typst/typst/blob/69dcc89d84176838c293b2d59747cd65e28843ad/crates/typst-cli/src/fonts.rs
@@ -95,10 +99,12 @@ struct DummyWorld {
     book: LazyHash<FontBook>,
     main: Source,
     fonts: Vec<FontSlot>,
+    /// Modules uploaded to the guild, by name.
+    modules: BTreeMap<String, String>,
 }
 
 impl DummyWorld {
-    fn new(main: String) -> Self {
+    fn new(main: String, modules: BTreeMap<String, String>) -> Self {
         let mut loader = FontLoader::new();
         loader.load_embedded_fonts();
         loader.load_system_fonts();
@@ -108,10 +114,36 @@ impl DummyWorld {
             book: LazyHash::new(loader.book),
             main: Source::detached(main),
             fonts: loader.fonts,
+            modules,
         }
     }
+
+    /// Replaces the document to compile, keeping the loaded fonts.
+    fn set_main(&mut self, main: String) {
+        self.main = Source::detached(main);
+    }
+
+    /// Looks up a module uploaded to the guild, if the id refers to one.
+    fn guild_module(&self, id: FileId) -> Option<FileResult<&str>> {
+        if id.package().is_some() {
+            return None;
+        }
+
+        let path = id.vpath().as_rootless_path();
+        let name = path.to_str()?.strip_prefix(GUILD_MODULE_PREFIX)?;
+
+        Some(
+            self.modules
+                .get(name)
+                .map(String::as_str)
+                .ok_or_else(|| FileError::NotFound(path.into())),
+        )
+    }
 }
 
+/// Prefix of import paths referring to modules uploaded to the guild, e.g. `guild:notation.typ`.
+const GUILD_MODULE_PREFIX: &str = "guild:";
+
 fn load_package_file(id: FileId) -> FileResult<Bytes> {
     let Some(package) = id.package() else {
         return Err(FileError::Other(Some(
@@ -155,6 +187,9 @@ impl World for DummyWorld {
         if id == self.main.id() {
             return Ok(self.main.clone());
         }
+        if let Some(module) = self.guild_module(id) {
+            return Ok(Source::new(id, module?.to_string()));
+        }
 
         let bytes = load_package_file(id)?;
         let text = String::from_utf8(bytes.to_vec())?;
@@ -162,6 +197,10 @@ impl World for DummyWorld {
     }
 
     fn file(&self, id: FileId) -> FileResult<Bytes> {
+        if let Some(module) = self.guild_module(id) {
+            return Ok(module?.as_bytes().to_vec().into());
+        }
+
         load_package_file(id)
     }
 
@@ -174,18 +213,63 @@ impl World for DummyWorld {
     }
 }
 
-pub fn render_to_png(typst: String) -> anyhow::Result<Vec<u8>> {
-    let typst = [
-        "#set page(width: 11.5cm, height: auto, margin: (x: 1mm, y: 2mm))",
-        "#set page(fill: rgb(\"#313338\"))", // Discord background color
-        "#set text(white)",
-        &typst,
-    ]
-    .join("\n");
+/// What the bot sends to the renderer on stdin.
+#[derive(Serialize, Deserialize)]
+struct TypstJob {
+    typst: String,
+    /// Modules uploaded to the guild, importable with [`GUILD_MODULE_PREFIX`].
+    modules: BTreeMap<String, String>,
+    request: RenderRequest,
+}
+
+/// Horizontal extent of everything drawn in a frame, relative to its origin.
+fn content_bounds(frame: &Frame) -> Option<(Abs, Abs)> {
+    let mut bounds: Option<(Abs, Abs)> = None;
+    let mut include = |left: Abs, right: Abs| {
+        bounds = Some(match bounds {
+            Some((min, max)) => (min.min(left), max.max(right)),
+            None => (left, right),
+        });
+    };
+
+    for (Point { x, .. }, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => {
+                let offset = *x + group.transform.tx;
+                let inner = if group.clip_path.is_some() {
+                    // Clipped content is cut off at the group's bounds anyway
+                    Some((Abs::zero(), group.frame.width()))
+                } else {
+                    content_bounds(&group.frame)
+                };
+                if let Some((left, right)) = inner {
+                    include(offset + left, offset + right);
+                }
+            }
+            FrameItem::Text(text) => include(*x, *x + text.width()),
+            FrameItem::Shape(shape, _) => include(*x, *x + shape.geometry.bbox_size().x),
+            FrameItem::Image(_, size, _) => include(*x, *x + size.x),
+            FrameItem::Link(..) | FrameItem::Tag(_) => {}
+        }
+    }
+
+    bounds
+}
+
+/// Whether content sticks out of the sides of any page and is cut off in the image.
+fn overflows(document: &Document) -> bool {
+    // Glyphs may legitimately overhang their advance a little
+    let tolerance = Abs::pt(1.0);
 
-    let world = DummyWorld::new(typst);
+    document.pages.iter().any(|page| {
+        content_bounds(&page.frame).is_some_and(|(left, right)| {
+            left < -tolerance || right > page.frame.width() + tolerance
+        })
+    })
+}
 
-    let document = typst::compile(&world).output.map_err(|errs| {
+fn compile(world: &DummyWorld) -> anyhow::Result<Document> {
+    typst::compile(world).output.map_err(|errs| {
         // Errors could be nicer, e.g.
         // https://github.com/typst/typst/blob/be12762d942e978ddf2e0ac5c34125264ab483b7/crates/typst-cli/src/compile.rs#L461-L501
         let errs = errs
@@ -194,47 +278,92 @@ pub fn render_to_png(typst: String) -> anyhow::Result<Vec<u8>> {
             .collect::<Vec<_>>()
             .join("\n\n");
         anyhow!("Failed to compile typst code:\n\n{errs}")
-    })?;
+    })
+}
 
-    // Color doesn't matter, it is already set by the document itself
-    let png = typst_render::render_merged(&document, 4.0, Abs::zero(), None).encode_png()?;
+/// Typst measures in points, of which there are 72 per inch.
+const POINTS_PER_INCH: f32 = 72.0;
 
-    Ok(png)
+fn render_to_image(job: TypstJob) -> anyhow::Result<RendererOutput> {
+    let request = job.request;
+    let fill = match request.appearance.background() {
+        Some(color) => format!("#set page(fill: rgb(\"#{color}\"))"),
+        None => "#set page(fill: none)".to_string(),
+    };
+    let text = format!("#set text(rgb(\"#{}\"))", request.appearance.foreground());
+    let document_with_width = |page_width: &str| {
+        let page =
+            format!("#set page(width: {page_width}, height: auto, margin: (x: 1mm, y: 2mm))");
+        [&page, &fill, &text, &job.typst]
+            .map(String::as_str)
+            .join("\n")
+    };
+
+    // Shrink-wrap the content first, only wrap lines if that turns out too wide
+    let mut world = DummyWorld::new(document_with_width("auto"), job.modules.clone());
+    let mut document = compile(&world)?;
+    let max_width = Abs::cm(request.width_cm);
+    if document
+        .pages
+        .iter()
+        .any(|page| page.frame.width() > max_width)
+    {
+        world.set_main(document_with_width(&format!("{}cm", request.width_cm)));
+        document = compile(&world)?;
+    }
+
+    // Color doesn't matter, it is already set by the document itself. Pages without fill stay
+    // transparent.
+    let pixel_per_pt = request.dpi as f32 / POINTS_PER_INCH;
+    let png =
+        typst_render::render_merged(&document, pixel_per_pt, Abs::zero(), None).encode_png()?;
+    let height: Abs = document.pages.iter().map(|page| page.frame.height()).sum();
+
+    Ok(RendererOutput {
+        image: request.format.encode(png, request.appearance)?,
+        overflow: overflows(&document),
+        too_tall: height > Abs::cm(MAX_HEIGHT_CM),
+    })
 }
 
 pub struct RenderedTypst {
-    pub png: Vec<u8>,
+    pub image: Vec<u8>,
+    /// Whether the content did not fit and a wider render might look better.
+    pub overflow: bool,
+    pub too_tall: bool,
 }
 
 pub fn run_renderer() {
-    let mut typst = String::new();
+    let mut job = String::new();
     std::io::stdin()
-        .read_to_string(&mut typst)
+        .read_to_string(&mut job)
         .expect("could not read stdin");
+    let job: TypstJob = serde_json::from_str(&job).expect("could not parse job");
 
-    match render_to_png(typst) {
-        Ok(png) => {
-            std::io::stdout()
-                .write_all(&png)
-                .expect("could not write image");
-        }
-        Err(err) => {
-            eprintln!("Error rendering typst: {err}");
-            std::process::exit(1);
-        }
-    }
+    docker::write_renderer_output(render_to_image(job));
 }
 
 pub async fn render_typst(
-    context_id: u64,
+    context_id: String,
     renderer_image: String,
     typst: String,
+    modules: BTreeMap<String, String>,
+    request: &RenderRequest,
 ) -> anyhow::Result<RenderedTypst> {
+    let job = serde_json::to_string(&TypstJob {
+        typst,
+        modules,
+        request: *request,
+    })?;
     let output = DockerCommand::new(renderer_image, format!("slave-typst-{context_id}"))
         .arg("render-typst")
-        .run(&typst)
+        .run(&job)
         .await?;
 
-    let png = output.stdout.to_vec();
-    Ok(RenderedTypst { png })
+    let output = docker::read_renderer_output(&output)?;
+    Ok(RenderedTypst {
+        image: output.image,
+        overflow: output.overflow,
+        too_tall: output.too_tall,
+    })
 }